@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+use clap;
+use clap::{App, Arg, ArgMatches};
+use render::{Normalization, Colormap};
+use h5slice::Query;
+use h5meta::Layout;
+
+#[derive(PartialEq)]
+pub enum Backend {
+    Server,
+    Native
+}
+
+pub struct Options {
+    /// `h5ls -r` listing to read the group tree from, instead of the file itself.
+    pub metadata: Option<PathBuf>,
+    pub h5file: PathBuf,
+    pub server: String,
+    pub backend: Backend,
+    pub geometry: (u32, u32),
+    pub normalization: Option<Normalization>,
+    pub colormap: Colormap,
+    pub fullscreen: bool,
+    pub open: Option<String>,
+    /// Slab template applied to datasets of matching rank.
+    pub select: Option<Query>,
+    /// Axis order assumed for datasets of matching rank, instead of guessing.
+    pub layout: Option<Layout>,
+    /// Dataset captioning the items of the open one, instead of a sibling found by name.
+    pub labels: Option<String>,
+    /// File naming the classes, one per line, label 0 first.
+    pub class_names: Option<PathBuf>,
+    /// Memory budget of the image cache in bytes.
+    pub cache_size: usize,
+    /// Directory keeping fetched items across sessions, and its size limit in bytes.
+    pub disk_cache: Option<PathBuf>,
+    pub disk_cache_size: u64
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let matches = App::new("piston-gui")
+            .version(env!("CARGO_PKG_VERSION"))
+            .about("Browse HDF5 datasets as image grids.")
+            .arg(Arg::with_name("H5FILE")
+                .help("HDF5 file to browse")
+                .required(true)
+                .validator(is_file))
+            .arg(Arg::with_name("metadata")
+                .short("m").long("metadata")
+                .value_name("FILE")
+                .help("Metadata listing of H5FILE produced by `h5ls -r` [default: read H5FILE itself \
+                    with the native feature, H5FILE.txt otherwise]")
+                .takes_value(true)
+                .validator(is_file))
+            .arg(Arg::with_name("server")
+                .short("s").long("server")
+                .value_name("HOST:PORT")
+                .help("Address of the slice server")
+                .default_value("localhost:8000")
+                .validator(is_address))
+            .arg(Arg::with_name("backend")
+                .short("b").long("backend")
+                .value_name("BACKEND")
+                .help("Where pixel data comes from: the slice server, or H5FILE read in-process")
+                .possible_values(&["server", "native"])
+                .default_value("server")
+                .validator(|s| if s != "native" || cfg!(feature = "native") { Ok(()) }
+                    else { Err(String::from("This build has no native backend; rebuild with `--features native`.")) }))
+            .arg(Arg::with_name("geometry")
+                .short("g").long("geometry")
+                .value_name("WxH")
+                .help("Window size")
+                .default_value("1920x1080")
+                .validator(|s| parse_geometry(&s).map(|_| ())))
+            .arg(Arg::with_name("normalize")
+                .short("n").long("normalize")
+                .value_name("MODE")
+                .help("Initial value-to-pixel mapping: identity, image, dataset, window:MIN,MAX, \
+                    percentile:LOW,HIGH or standard:MEAN,.../STD,... [default: identity for uint8, image otherwise]")
+                .takes_value(true)
+                .validator(|s| s.parse::<Normalization>().map(|_| ())))
+            .arg(Arg::with_name("colormap")
+                .short("c").long("colormap")
+                .value_name("COLORMAP")
+                .help("Colors of single-channel images, cycled at runtime with K")
+                .possible_values(&["gray", "viridis", "magma", "jet", "rdbu"])
+                .default_value("gray"))
+            .arg(Arg::with_name("windowed")
+                .short("w").long("windowed")
+                .help("Run in a window instead of fullscreen"))
+            .arg(Arg::with_name("open")
+                .short("o").long("open")
+                .value_name("H5PATH")
+                .help("Group or dataset to open on startup, e.g. /train/images")
+                .takes_value(true)
+                .validator(|s| if s.starts_with("/") { Ok(()) }
+                    else { Err(format!("\"{}\" is not an absolute HDF5 path.", s)) }))
+            .arg(Arg::with_name("select")
+                .long("select")
+                .value_name("TEMPLATE")
+                .help("Per-axis selection of datasets with as many axes, `*` marking the axis to page through, \
+                    e.g. `*,5,:,:` for channel 5 of NCHW or `:,*,:` for slices along the second axis of a volume")
+                .takes_value(true)
+                .validator(|s| Query::template(&s).map(|_| ())))
+            .arg(Arg::with_name("layout")
+                .short("l").long("layout")
+                .value_name("LAYOUT")
+                .help("Axis order of image datasets: NHWC, NCHW, NHW, or HW and HWC for single images; \
+                    or how to show numbers: value, line, histogram or table \
+                    [default: guessed from the shape, toggled at runtime with L]")
+                .takes_value(true)
+                .validator(|s| s.parse::<Layout>().map(|_| ())))
+            .arg(Arg::with_name("labels")
+                .long("labels")
+                .value_name("H5PATH")
+                .help("1-D dataset holding the labels of the items of opened datasets [default: a sibling dataset \
+                    as long, named like labels, e.g. /train/labels for /train/images]")
+                .takes_value(true)
+                .validator(|s| if s.starts_with("/") { Ok(()) }
+                    else { Err(format!("\"{}\" is not an absolute HDF5 path.", s)) }))
+            .arg(Arg::with_name("class-names")
+                .long("class-names")
+                .value_name("FILE")
+                .help("Class names to caption labels with, one per line [default: the class_names, classes \
+                    or names attribute of the labels or the images]")
+                .takes_value(true)
+                .validator(is_file))
+            .arg(Arg::with_name("cache-mb")
+                .long("cache-mb")
+                .value_name("MB")
+                .help("Memory budget of the image cache")
+                .default_value("1024")
+                .validator(|s| s.parse::<usize>().map(|_| ())
+                    .map_err(|_| format!("Invalid cache size \"{}\", expected megabytes.", s))))
+            .arg(Arg::with_name("disk-cache")
+                .long("disk-cache")
+                .value_name("DIR")
+                .help("Keep fetched items in DIR across sessions, so revisited datasets load without the server")
+                .takes_value(true))
+            .arg(Arg::with_name("disk-cache-mb")
+                .long("disk-cache-mb")
+                .value_name("MB")
+                .help("Size limit of the disk cache")
+                .default_value("4096")
+                .validator(|s| s.parse::<u64>().map(|_| ())
+                    .map_err(|_| format!("Invalid cache size \"{}\", expected megabytes.", s))))
+            .get_matches();
+        Options::from_matches(&matches)
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Options {
+        let h5file = PathBuf::from(matches.value_of("H5FILE").unwrap());
+        let metadata = match matches.value_of("metadata") {
+            Some(fname) => Some(PathBuf::from(fname)),
+            None if cfg!(feature = "native") => None,
+            None => {
+                let mut fname = h5file.clone().into_os_string();
+                fname.push(".txt");
+                let fname = PathBuf::from(fname);
+                if !fname.is_file() {
+                    clap::Error::value_validation_auto(format!(
+                        "No metadata listing found at {}; generate one with `h5ls -r {} > {}` or pass --metadata.",
+                        fname.display(), h5file.display(), fname.display())).exit();
+                }
+                Some(fname)
+            }
+        };
+        Options {
+            metadata: metadata,
+            h5file: h5file,
+            server: String::from(matches.value_of("server").unwrap()),
+            backend: match matches.value_of("backend").unwrap() {
+                "native" => Backend::Native,
+                _ => Backend::Server
+            },
+            geometry: parse_geometry(matches.value_of("geometry").unwrap()).unwrap(),
+            normalization: matches.value_of("normalize").map(|s| s.parse().unwrap()),
+            colormap: matches.value_of("colormap").unwrap().parse().unwrap(),
+            fullscreen: !matches.is_present("windowed"),
+            open: matches.value_of("open").map(String::from),
+            select: matches.value_of("select").map(|s| Query::template(s).unwrap()),
+            layout: matches.value_of("layout").map(|s| s.parse().unwrap()),
+            labels: matches.value_of("labels").map(String::from),
+            class_names: matches.value_of("class-names").map(PathBuf::from),
+            cache_size: matches.value_of("cache-mb").unwrap().parse::<usize>().unwrap() << 20,
+            disk_cache: matches.value_of("disk-cache").map(PathBuf::from),
+            disk_cache_size: matches.value_of("disk-cache-mb").unwrap().parse::<u64>().unwrap() << 20
+        }
+    }
+}
+
+fn is_file(fname: String) -> Result<(), String> {
+    if Path::new(&fname).is_file() { Ok(()) }
+    else { Err(format!("File not found: {}", fname)) }
+}
+
+/// Only checks the `host:port` syntax, the host is resolved on connecting.
+fn is_address(addr: String) -> Result<(), String> {
+    match addr.rfind(':') {
+        Some(i) if i > 0 && addr[i + 1..].parse::<u16>().is_ok() => Ok(()),
+        _ => Err(format!("Invalid server address \"{}\", expected HOST:PORT such as localhost:9000.", addr))
+    }
+}
+
+fn parse_geometry(s: &str) -> Result<(u32, u32), String> {
+    let dims: Vec<&str> = s.split('x').collect();
+    if dims.len() == 2 {
+        if let (Ok(w), Ok(h)) = (dims[0].parse::<u32>(), dims[1].parse::<u32>()) {
+            if w > 0 && h > 0 {
+                return Ok((w, h));
+            }
+        }
+    }
+    Err(format!("Invalid window size \"{}\", expected WIDTHxHEIGHT such as 1920x1080.", s))
+}
@@ -0,0 +1,140 @@
+use std;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use filetime::{FileTime, set_file_mtime};
+use h5slice::{H5URI, ItemShape, Dtype, ByteOrder, decode};
+
+/// Decoded items kept on disk across sessions, one gzipped file of f32 per
+/// item. Files are named by a hash of the item's H5URI, its shape and the
+/// modification time and size of its HDF5 file, so editing the file
+/// invalidates them. Beyond the size limit the least recently used files go
+/// first, going by modification times that every load renews.
+pub struct DiskCache {
+    dir: PathBuf,
+    limit: u64,
+    /// Bytes of the files in `dir`.
+    used: Mutex<u64>
+}
+
+impl DiskCache {
+    /// Opens the cache in `dir`, creating the directory if needed.
+    pub fn open<P: AsRef<Path>>(dir: P, limit: u64) -> io::Result<DiskCache> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut used = 0;
+        for entry in fs::read_dir(&dir)? {
+            used += entry?.metadata()?.len();
+        }
+        Ok(DiskCache { dir: dir, limit: limit, used: Mutex::new(used) })
+    }
+
+    /// Identifies item `uri` of shape `item` in the current version of its
+    /// file, if the file can be found here.
+    fn key(uri: &H5URI, item: &ItemShape) -> Option<String> {
+        let meta = fs::metadata(&uri.path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(format!("{}\t{}\t{}.{:09}\t{}", uri.to_string(), item,
+            mtime.as_secs(), mtime.subsec_nanos(), meta.len()))
+    }
+
+    fn file_name(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.gz", fnv1a(key.as_bytes())))
+    }
+
+    /// Elements of the single item `uri`, if cached.
+    pub fn load(&self, uri: &H5URI, item: &ItemShape) -> Option<Vec<f32>> {
+        let key = DiskCache::key(uri, item)?;
+        let fname = self.file_name(&key);
+        let file = fs::File::open(&fname).ok()?;
+        let mut buffer = Vec::new();
+        GzDecoder::new(file).read_to_end(&mut buffer).ok()?;
+        // the key heads the file in case of hash collisions
+        let header_end = buffer.iter().position(|&b| b == b'\n')?;
+        if &buffer[..header_end] != key.as_bytes() || (buffer.len() - header_end - 1) % 4 != 0 {
+            return None;
+        }
+        // marks the file used, for eviction; it stays valid if that fails
+        let _ = set_file_mtime(&fname, FileTime::now());
+        Some(decode(&buffer[header_end+1..], &Dtype::F4, ByteOrder::Little))
+    }
+
+    /// Saves the elements of the single item `uri`.
+    pub fn store(&self, uri: &H5URI, item: &ItemShape, data: &[f32]) -> io::Result<()> {
+        let key = match DiskCache::key(uri, item) {
+            Some(key) => key,
+            None => return Ok(())
+        };
+        let fname = self.file_name(&key);
+        // written aside and renamed, so no reader ever sees half a file
+        let tmp = fname.with_extension(format!("{}.tmp", std::process::id()));
+        {
+            let mut encoder = GzEncoder::new(fs::File::create(&tmp)?, Compression::fast());
+            encoder.write_all(key.as_bytes())?;
+            encoder.write_all(b"\n")?;
+            let mut bytes = Vec::with_capacity(data.len() * 4);
+            for x in data {
+                let bits = x.to_bits();
+                bytes.extend_from_slice(&[bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8]);
+            }
+            encoder.write_all(&bytes)?;
+            encoder.finish()?;
+        }
+        // replacing a file frees its bytes
+        let replaced = fs::metadata(&fname).map(|meta| meta.len()).unwrap_or(0);
+        fs::rename(&tmp, &fname)?;
+        let mut used = self.used.lock().unwrap();
+        *used = (*used + fs::metadata(&fname)?.len()).saturating_sub(replaced);
+        if *used > self.limit {
+            *used = self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Saves each of the items selected by `uri`, as far as `data` holds them.
+    pub fn store_many(&self, uri: &H5URI, item: &ItemShape, data: &[f32]) -> io::Result<()> {
+        let im_size = item.size();
+        let mut uri_one = uri.clone();
+        for (i, data) in uri.query.indices().zip(data.chunks(im_size)) {
+            if data.len() == im_size {
+                uri_one.query = uri.query.item(i);
+                self.store(&uri_one, item, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the least recently used files, by modification time, until 90%
+    /// of the limit is left. Returns the bytes still used.
+    fn evict(&self) -> io::Result<u64> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if entry.path().extension().map(|x| x == "gz").unwrap_or(false) {
+                files.push((meta.modified()?, entry.path(), meta.len()));
+            }
+        }
+        files.sort();
+        let mut used: u64 = files.iter().map(|f| f.2).sum();
+        for (_, path, len) in files {
+            if used <= self.limit / 10 * 9 {
+                break;
+            }
+            fs::remove_file(path)?;
+            used -= len;
+        }
+        Ok(used)
+    }
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -6,6 +6,7 @@ use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
 use regex::Regex;
+use h5slice::{Dtype, ByteOrder, ItemShape};
 
 type Shape = Vec<usize>;
 
@@ -37,17 +38,58 @@ impl H5Group {
         }
     }
 
+    /// Name of the child dataset likeliest to hold the labels of the `len`
+    /// items of sibling dataset `name`: one as long, named after `name` like
+    /// `y_train` after `x_train`, or else named like labels.
+    pub fn find_labels(&self, name: &str, len: usize) -> Option<String> {
+        let candidates: Vec<&String> = self.children.iter()
+            .filter(|(child, obj)| *child != name && match obj {
+                H5Obj::Dataset(d) => d.shape == vec![len],
+                H5Obj::Group(_) => false
+            })
+            .map(|(child, _)| child).collect();
+        let mut paired = vec![name.replace("images", "labels"), name.replace("image", "label")];
+        if name.starts_with("x") || name.starts_with("X") {
+            paired.push(format!("y{}", &name[1..]));
+        }
+        let named = |child: &str| {
+            let child = child.to_lowercase();
+            ["labels", "label", "targets", "target", "classes", "class", "y"].contains(&child.as_ref())
+        };
+        let similar = |child: &str| {
+            let child = child.to_lowercase();
+            child.contains("label") || child.contains("target")
+        };
+        candidates.iter().find(|c| paired.contains(c))
+            .or(candidates.iter().find(|c| named(c)))
+            .or(candidates.iter().find(|c| similar(c)))
+            .map(|c| c.to_string())
+    }
+
+    /// Parses `h5ls -r` output. Listings made with `h5ls -rv` additionally
+    /// provide dtypes, chunking, filters and attributes of the datasets.
     pub fn parse<P: AsRef<Path>>(fname: P) -> std::io::Result<H5Obj> {
         let rule = Regex::new(r"^(?P<name>[^ ]+)\s+(?P<type>Group|Dataset)").unwrap();
+        let rule_type = Regex::new(r"^ {4}Type:\s+(?P<type>.+)$").unwrap();
+        let rule_chunks = Regex::new(r"^ {4}Chunks:\s+\{(?P<chunks>[0-9, ]+)\}").unwrap();
+        let rule_filter = Regex::new(r"^ {4}Filter-\d+:\s+(?P<name>[a-z_0-9]+)-\d+(?:\s+OPT)?(?:\s+\{(?P<params>[^}]*)\})?").unwrap();
+        let rule_attr = Regex::new(r"^ {4}Attribute:\s+(?P<name>\S+)").unwrap();
+        let rule_data = Regex::new(r"^ {8}Data:\s*(?P<data>.*)$").unwrap();
+        let rule_data_more = Regex::new(r"^ {12,}(?:\([0-9,]+\)\s*)?(?P<data>.+)$").unwrap();
         let file = File::open(fname)?;
         let reader = BufReader::new(file);
         let mut root = H5Group { name: String::from("/"), children: BTreeMap::new() };
         let mut spath = PathBuf::from(&root.name);
+        // the dataset (a child of spath) and attribute verbose lines refer to
+        let mut dataset: Option<String> = None;
+        let mut attr: Option<String> = None;
         for ll in reader.lines() {
             let line = ll?;
             let m = rule.captures(&line);
             match m {
                 Some(captures) => {
+                    attr = None;
+                    dataset = None;
                     match &captures["type"] {
                         "Group" => {
                             let full_name = &captures["name"];
@@ -77,14 +119,15 @@ impl H5Group {
                         },
                         "Dataset" => {
                             let full_name = &captures["name"];
-                            let rule_dataset = Regex::new(r"^(?P<name>[^ ]+)\s+Dataset\s+\{(?P<shape>[0-9, ]*|SCALAR)\}$").unwrap();
+                            let rule_dataset = Regex::new(r"^(?P<name>[^ ]+)\s+Dataset\s+\{(?P<shape>[0-9, /Inf]*|SCALAR)\}$").unwrap();
                             let m = rule_dataset.captures(&line).expect("Malformed dataset metadata.");
                             // TODO could be scalar
+                            // verbose listings give current/maximum sizes
                             let shape: Shape =
                                 if &m["shape"] == "SCALAR" { Vec::new() }
                                 else {
                                     m["shape"].split(", ")
-                                    .map(|x| x.parse().expect("Error occurred when parsing dataset shape."))
+                                    .map(|x| x.split('/').next().unwrap().parse().expect("Error occurred when parsing dataset shape."))
                                     .collect()
                                 };
                             if cfg!(debug_assertions) {
@@ -96,12 +139,50 @@ impl H5Group {
                             // ? optimize by keeping track of stack top?
                             root.locate_mut(&spath).children.insert(
                                 dataset_name.clone(),
-                                H5Obj::from(H5Dataset { name: dataset_name.clone(), shape: shape }));
+                                H5Obj::from(H5Dataset::new(dataset_name.clone(), shape)));
+                            dataset = Some(dataset_name);
                         },
                         _ => ()
                     }
                 }
-                None => ()
+                None => if let Some(dataset_name) = &dataset {
+                    let d = root.locate_mut(&spath).children.get_mut(dataset_name).unwrap().to_dataset_mut();
+                    if let Some(m) = rule_attr.captures(&line) {
+                        attr = Some(String::from(&m["name"]));
+                    }
+                    else if let Some(m) = rule_type.captures(&line) {
+                        attr = None;
+                        let (dtype, byte_order) = parse_type(&m["type"]);
+                        d.dtype = dtype;
+                        d.type_name = Some(String::from(&m["type"]));
+                        d.byte_order = byte_order;
+                    }
+                    else if let Some(m) = rule_chunks.captures(&line) {
+                        attr = None;
+                        d.chunks = m["chunks"].split(", ").map(|x| x.parse().ok()).collect();
+                    }
+                    else if let Some(m) = rule_filter.captures(&line) {
+                        attr = None;
+                        let name = &m["name"];
+                        if d.compression.is_none() && is_compression(name) {
+                            d.compression = Some(match m.name("params") {
+                                Some(params) => format!("{}({})", name, params.as_str()),
+                                None => String::from(name)
+                            });
+                        }
+                    }
+                    else if let Some(attr_name) = &attr {
+                        let m = rule_data.captures(&line).or_else(|| rule_data_more.captures(&line));
+                        if let Some(value) = m.and_then(|m| H5Attr::parse(&m["data"])) {
+                            if d.attrs.contains_key(attr_name) {
+                                d.attrs.get_mut(attr_name).unwrap().extend(value);
+                            }
+                            else {
+                                d.attrs.insert(attr_name.clone(), value);
+                            }
+                        }
+                    }
+                }
             };
             
         }
@@ -109,12 +190,152 @@ impl H5Group {
     }
 }
 
+/// Value of an HDF5 attribute, as far as the viewer cares.
+pub enum H5Attr {
+    Text(Vec<String>),
+    Number(Vec<f64>)
+}
+
+impl std::fmt::Display for H5Attr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let items: Vec<String> = match self {
+            H5Attr::Text(v) => v.clone(),
+            H5Attr::Number(v) => v.iter().map(|x| x.to_string()).collect()
+        };
+        if items.len() == 1 { write!(f, "{}", items[0]) }
+        else { write!(f, "[{}]", items.join(", ")) }
+    }
+}
+
+impl H5Attr {
+    /// Parses the values h5ls prints after `Data:`, e.g. `"cat", "dog"` or `0.5, 1`.
+    fn parse(data: &str) -> Option<H5Attr> {
+        let data = data.trim().trim_end_matches(',');
+        if data.is_empty() {
+            None
+        }
+        else if data.starts_with('"') {
+            let rule = Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap();
+            Some(H5Attr::Text(rule.captures_iter(data).map(|c| String::from(&c[1])).collect()))
+        }
+        else {
+            data.split(',').map(|x| x.trim().parse::<f64>()).collect::<Result<Vec<f64>, _>>()
+                .ok().map(H5Attr::Number)
+        }
+    }
+
+    /// Appends values continued on another line.
+    fn extend(&mut self, more: H5Attr) {
+        match (self, more) {
+            (H5Attr::Text(v), H5Attr::Text(mut w)) => v.append(&mut w),
+            (H5Attr::Number(v), H5Attr::Number(mut w)) => v.append(&mut w),
+            _ => ()
+        }
+    }
+}
+
+/// Element type and byte order of an h5ls type description such as
+/// `native float` or `IEEE 64-bit big-endian float`.
+fn parse_type(desc: &str) -> (Option<Dtype>, Option<ByteOrder>) {
+    let rule = Regex::new(r"^(?:IEEE )?(?P<bits>\d+)-bit (?P<order>little|big)-endian (?P<kind>float|integer|unsigned integer)$").unwrap();
+    if let Some(m) = rule.captures(desc) {
+        let kind = match &m["kind"] { "float" => "f", "integer" => "i", _ => "u" };
+        let order = if &m["order"] == "big" { ByteOrder::Big } else { ByteOrder::Little };
+        return (to_dtype(kind, m["bits"].parse().unwrap()), Some(order));
+    }
+    let (kind, bits) = match desc.trim_start_matches("native ") {
+        "float" => ("f", 32),
+        "double" => ("f", 64),
+        "schar" | "signed char" | "char" => ("i", 8),
+        "uchar" | "unsigned char" => ("u", 8),
+        "short" => ("i", 16),
+        "ushort" | "unsigned short" => ("u", 16),
+        "int" => ("i", 32),
+        "uint" | "unsigned int" => ("u", 32),
+        "long" | "llong" => ("i", 64),
+        "ulong" | "ullong" | "unsigned long" => ("u", 64),
+        _ => return (None, None)
+    };
+    let order = if cfg!(target_endian = "big") { ByteOrder::Big } else { ByteOrder::Little };
+    (to_dtype(kind, bits), Some(order))
+}
+
+/// Dtype of `bits`-wide elements of kind `f`, `i` or `u`, if supported.
+/// Integers wider than any integer dtype, like numpy's default int64, are
+/// requested as f8 and converted on read.
+pub fn to_dtype(kind: &str, bits: usize) -> Option<Dtype> {
+    match (kind, bits) {
+        ("i", 64) | ("u", 32) | ("u", 64) => Some(Dtype::F8),
+        _ => format!("{}{}", kind, bits / 8).parse().ok()
+    }
+}
+
+fn is_compression(filter: &str) -> bool {
+    match filter {
+        "shuffle" | "fletcher32" | "nbit" | "scaleoffset" => false,
+        _ => true
+    }
+}
+
 pub struct H5Dataset {
     pub name: String,
-    pub shape: Shape
+    pub shape: Shape,
+    /// Element type, when known and supported.
+    pub dtype: Option<Dtype>,
+    /// Element type as listed, supported or not.
+    pub type_name: Option<String>,
+    /// Byte order the elements are stored in.
+    pub byte_order: Option<ByteOrder>,
+    /// Chunk shape of chunked datasets.
+    pub chunks: Option<Shape>,
+    /// Compression filter with its parameters, e.g. `deflate(4)`.
+    pub compression: Option<String>,
+    pub attrs: BTreeMap<String, H5Attr>
 }
 
 impl H5Dataset {
+    pub fn new(name: String, shape: Shape) -> H5Dataset {
+        H5Dataset {
+            name: name,
+            shape: shape,
+            dtype: None,
+            type_name: None,
+            byte_order: None,
+            chunks: None,
+            compression: None,
+            attrs: BTreeMap::new()
+        }
+    }
+
+    /// Class names listed in a `class_names`, `classes` or `names` attribute.
+    pub fn class_names(&self) -> Option<Vec<String>> {
+        ["class_names", "classes", "names"].iter().filter_map(|key| match self.attrs.get(*key) {
+            Some(H5Attr::Text(names)) => Some(names.clone()),
+            _ => None
+        }).next()
+    }
+
+    /// One-line summary of the storage properties known about this dataset.
+    pub fn storage_to_string(&self) -> String {
+        let mut ret = match &self.dtype {
+            Some(dtype) => dtype.to_string(),
+            None => String::from("?")
+        };
+        if let Some(ByteOrder::Big) = self.byte_order {
+            ret.push_str(" BE");
+        }
+        if let Some(chunks) = &self.chunks {
+            ret.push_str(&format!(" chunks({})", H5DatasetFormat::shape_to_string(chunks)));
+        }
+        if let Some(compression) = &self.compression {
+            ret.push_str(&format!(" {}", compression));
+        }
+        if !self.attrs.is_empty() {
+            ret.push_str(&format!(" +{} attrs", self.attrs.len()));
+        }
+        ret
+    }
+
     #[deprecated]
     #[allow(dead_code)]
     pub fn resolution(&self) -> Option<Resolution> {
@@ -145,6 +366,11 @@ impl H5Obj {
         else { panic!("Failed to cast H5Obj into H5Group.") }
     }
 
+    fn to_dataset_mut(&mut self) -> &mut H5Dataset {
+        if let H5Obj::Dataset(d) = self { d }
+        else { panic!("Failed to cast H5Obj into H5Dataset.") }
+    }
+
     fn to_group(&self) -> &H5Group {
         if let H5Obj::Group(g) = self { g }
         else { panic!("Failed to cast H5Obj into H5Group.") }
@@ -178,6 +404,22 @@ impl H5Obj {
         }
     }
 
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<&H5Obj> {
+        let path = path.as_ref();
+        let mut components = path.components();
+        if path.is_absolute() && self.name() == "/" {
+            components.next(); // skip root
+        }
+        let mut obj = self;
+        for component in components {
+            match obj {
+                H5Obj::Group(g) => obj = g.children.get(component.as_os_str().to_str()?)?,
+                H5Obj::Dataset(_) => return None
+            }
+        }
+        Some(obj)
+    }
+
     pub fn locate_group<P: AsRef<Path>>(&self, path: P) -> Option<&H5Group> {
         match self.locate(path) {
             H5Obj::Group(g) => Some(&g),
@@ -223,49 +465,162 @@ impl std::fmt::Display for Resolution {
     }
 }
 
+/// Order of the axes of a dataset of images, or how to plot a dataset of
+/// numbers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// Batch, height, width, channels, as saved from numpy or TensorFlow.
+    NHWC,
+    /// Batch, channels, height, width, as saved from PyTorch.
+    NCHW,
+    /// Batch of single channel images.
+    NHW,
+    /// One single channel image.
+    HW,
+    /// One image, channels last.
+    HWC,
+    /// One number.
+    Value,
+    /// Numbers against their index.
+    Line,
+    /// Counts of numbers.
+    Histogram,
+    /// Rows of numbers, as text.
+    Table
+}
+
+impl Layout {
+    pub fn rank(&self) -> usize {
+        match self {
+            Layout::NHWC | Layout::NCHW => 4,
+            Layout::NHW | Layout::HWC => 3,
+            Layout::HW | Layout::Table => 2,
+            Layout::Line | Layout::Histogram => 1,
+            Layout::Value => 0
+        }
+    }
+
+    /// Layouts `shape` can be read as, the likeliest first.
+    pub fn candidates(shape: &Shape) -> Vec<Layout> {
+        let is_channels = |n: usize| n == 1 || n == 3 || n == 4;
+        match shape.len() {
+            // channels last, unless only the second axis looks like channels
+            4 if is_channels(shape[1]) && !is_channels(shape[3]) => vec![Layout::NCHW, Layout::NHWC],
+            4 => vec![Layout::NHWC, Layout::NCHW],
+            // a last axis of 1, 3 or 4 is rather channels than width, unless the
+            // two last axes make square images, e.g. a stack of 3x3 kernels
+            3 if is_channels(shape[2]) && shape[1] != shape[2] => vec![Layout::HWC, Layout::NHW],
+            3 if is_channels(shape[2]) => vec![Layout::NHW, Layout::HWC],
+            3 => vec![Layout::NHW],
+            // as a heatmap
+            2 => vec![Layout::HW, Layout::Table],
+            1 => vec![Layout::Line, Layout::Histogram],
+            0 => vec![Layout::Value],
+            _ => Vec::new()
+        }
+    }
+
+    pub fn detect(shape: &Shape) -> Option<Layout> {
+        Layout::candidates(shape).first().cloned()
+    }
+
+    /// Whether the whole dataset is one image.
+    pub fn is_single(&self) -> bool {
+        *self != Layout::NHWC && *self != Layout::NCHW && *self != Layout::NHW
+    }
+
+    /// Whether the dataset is drawn from its numbers rather than as an image.
+    pub fn is_plot(&self) -> bool {
+        match self {
+            Layout::Value | Layout::Line | Layout::Histogram | Layout::Table => true,
+            _ => false
+        }
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layout, String> {
+        match s.to_uppercase().as_ref() {
+            "NHWC" => Ok(Layout::NHWC),
+            "NCHW" => Ok(Layout::NCHW),
+            "NHW" => Ok(Layout::NHW),
+            "HW" => Ok(Layout::HW),
+            "HWC" => Ok(Layout::HWC),
+            "VALUE" => Ok(Layout::Value),
+            "LINE" => Ok(Layout::Line),
+            "HISTOGRAM" => Ok(Layout::Histogram),
+            "TABLE" => Ok(Layout::Table),
+            _ => Err(format!("Unknown layout \"{}\", expected NHWC, NCHW, NHW, HW, HWC, value, line, histogram or table", s))
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct H5DatasetFormat<'a> {
     shape: &'a Shape,
+    pub layout: Layout,
     pub resolution: Resolution,
+    pub channels: usize,
     pub pagination_range: std::ops::Range<usize>,
     pub format: String
 }
 
 #[allow(dead_code)]
 impl<'a> H5DatasetFormat<'a> {
-    pub fn resolution_single_image(shape: &Shape) -> Option<Resolution> {
-        match shape.len() {
-            // shape when 2<=dims<=3: height width channels?
-            dims if dims >= 2 && dims <=3 =>
-                Some(Resolution{ width: shape[1], height: shape[0] }),
-            _ => None
-        }
+    pub fn batch(shape: &'a Shape) -> Option<H5DatasetFormat<'a>> {
+        Layout::detect(shape).and_then(|layout| H5DatasetFormat::with_layout(shape, layout))
     }
 
-    pub fn resolution_batch_images(shape: &Shape) -> Option<Resolution> {
-        match shape.len() {
-            // shape when 3<=dims<=4: batch height width channels?
-            dims if dims >= 3 && dims <=4 =>
-                Some(Resolution{ width: shape[2], height: shape[1] }),
-            _ => None
+    /// Reads `shape` as laid out in `layout`, if it has as many axes.
+    pub fn with_layout(shape: &'a Shape, layout: Layout) -> Option<H5DatasetFormat<'a>> {
+        if shape.len() != layout.rank() {
+            return None;
         }
+        let (n, height, width, channels) = match layout {
+            Layout::NHWC => (shape[0], shape[1], shape[2], shape[3]),
+            Layout::NCHW => (shape[0], shape[2], shape[3], shape[1]),
+            Layout::NHW => (shape[0], shape[1], shape[2], 1),
+            Layout::HW => (1, shape[0], shape[1], 1),
+            Layout::HWC => (1, shape[0], shape[1], shape[2]),
+            Layout::Value => (1, 1, 1, 1),
+            Layout::Line | Layout::Histogram => (1, 1, shape[0], 1),
+            Layout::Table => (1, shape[0], shape[1], 1)
+        };
+        Some(H5DatasetFormat {
+            shape: shape,
+            layout: layout,
+            resolution: Resolution { width: width, height: height },
+            channels: channels,
+            pagination_range: 0..n,
+            format: if layout.is_plot() { H5DatasetFormat::shape_to_format(shape) }
+                else { String::from(H5DatasetFormat::channels_to_format(channels)) }
+        })
     }
 
-    pub fn single(shape: &'a Shape) -> H5DatasetFormat<'a> {
-        H5DatasetFormat {
-            shape: shape,
-            pagination_range: 0..1,
-            format: H5DatasetFormat::shape_to_format(shape),
-            resolution: H5DatasetFormat::resolution_single_image(shape).unwrap()
+    /// How the elements of one item are laid out.
+    pub fn item_shape(&self) -> ItemShape {
+        ItemShape {
+            height: self.resolution.height,
+            width: self.resolution.width,
+            channels: self.channels,
+            channels_first: self.layout == Layout::NCHW
         }
     }
 
-    pub fn batch(shape: &'a Shape) -> H5DatasetFormat<'a> {
-        H5DatasetFormat {
-            shape: shape,
-            pagination_range: 0..shape[0],
-            format: H5DatasetFormat::shape_to_format(shape),
-            resolution: H5DatasetFormat::resolution_batch_images(shape).unwrap()
+    fn channels_to_format(channels: usize) -> &'static str {
+        match channels {
+            1 => "Gray",
+            3 => "Color",
+            4 => "Alpha",
+            _ => "Hyper"
         }
     }
 
@@ -275,15 +630,9 @@ impl<'a> H5DatasetFormat<'a> {
                 0 => "Param",
                 1 => "Scalar",
                 2 => "Vec",
-                3 => "Gray",
-                dims if dims >=4 =>
-                    match shape.iter().last().unwrap() {
-                        1 => "Gray",
-                        3 => "Color",
-                        4 => "Alpha",
-                        _ => "Hyper"
-                    }
-                _ => ""
+                3 | 4 => H5DatasetFormat::batch(shape)
+                    .map_or("Hyper", |format| H5DatasetFormat::channels_to_format(format.channels)),
+                _ => H5DatasetFormat::channels_to_format(*shape.iter().last().unwrap())
             })
     }
 
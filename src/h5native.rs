@@ -0,0 +1,228 @@
+use std;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use hdf5;
+use hdf5::types::{TypeDescriptor, IntSize, FloatSize, VarLenAscii, VarLenUnicode};
+use ndarray::{ArrayD, IxDyn, SliceInfo, SliceOrIndex};
+use h5slice::{SliceSource, H5URI, Query, AxisSel, Dtype, ByteOrder, FetchError};
+use h5meta::{H5Obj, H5Group, H5Dataset, H5Attr, to_dtype};
+
+/// Reads slices straight out of the HDF5 file named by `H5URI.path`, so no
+/// slice server is needed.
+pub struct H5File {
+    path: PathBuf
+}
+
+impl H5File {
+    pub fn new<P: AsRef<Path>>(path: P) -> H5File {
+        H5File { path: path.as_ref().to_path_buf() }
+    }
+}
+
+fn hdf5_error(e: hdf5::Error) -> FetchError {
+    FetchError::Source(e.to_string())
+}
+
+/// Hyperslab selecting the queried items, along with the axis they are taken
+/// along. Like the slice server, a batch running past the end of the dataset
+/// is cut short.
+fn selection(query: &Query, shape: &[usize]) -> Result<(Vec<SliceOrIndex>, usize), FetchError> {
+    let slice = |a: usize, b: usize, n: usize| SliceOrIndex::Slice {
+        start: a as isize, end: Some(std::cmp::min(b, n) as isize), step: 1
+    };
+    let mut sel = vec![SliceOrIndex::Slice { start: 0, end: None, step: 1 }; shape.len()];
+    let n = shape[0];
+    match query {
+        Query::One(idx) => sel[0] = slice(*idx, idx+1, n),
+        Query::Range(a, b) => sel[0] = slice(*a, *b, n),
+        Query::Batch(idx, len) => sel[0] = slice(*idx, idx+len, n),
+        Query::All => return Ok((sel, 0)),
+        Query::Slab { axis, sel: axes } => {
+            if axes.len() != shape.len() {
+                return Err(FetchError::UnsupportedQuery(query.to_string()));
+            }
+            for (k, (s, &n)) in axes.iter().zip(shape).enumerate() {
+                sel[k] = match *s {
+                    // the paged axis is kept even for one item, so it can lead
+                    AxisSel::Index(idx) if k == *axis => slice(idx, idx+1, n),
+                    AxisSel::Index(idx) => SliceOrIndex::Index(idx as isize),
+                    AxisSel::Range { start, end, step } => SliceOrIndex::Slice {
+                        start: start as isize,
+                        end: Some(std::cmp::min(end.unwrap_or(n), n) as isize),
+                        step: step as isize
+                    },
+                    AxisSel::All => SliceOrIndex::Slice { start: 0, end: None, step: 1 }
+                };
+            }
+            return Ok((sel, *axis));
+        }
+    }
+    Ok((sel, 0))
+}
+
+/// Reads the items selected by `query`, with the axis they are taken along
+/// moved first so that they come out one after another.
+fn read<T: hdf5::H5Type>(dataset: &hdf5::Dataset, query: &Query, shape: &[usize]) -> Result<ArrayD<T>, FetchError> {
+    if let Query::All = query {
+        // no hyperslab needed, which scalars could not have anyway
+        let data = dataset.read_raw::<T>().map_err(hdf5_error)?;
+        return ArrayD::from_shape_vec(IxDyn(shape), data).map_err(|e| FetchError::Source(e.to_string()));
+    }
+    let (sel, axis) = selection(query, shape)?;
+    // position of the paged axis once indexed axes are dropped
+    let axis = sel[..axis].iter().filter(|s| match s { SliceOrIndex::Index(_) => false, _ => true }).count();
+    let info = SliceInfo::<_, IxDyn>::new(sel).map_err(|e| FetchError::Source(e.to_string()))?;
+    let items: ArrayD<T> = dataset.read_slice(&info).map_err(hdf5_error)?;
+    if axis == 0 {
+        return Ok(items);
+    }
+    let mut order: Vec<usize> = (0..items.ndim()).collect();
+    order.remove(axis);
+    order.insert(0, axis);
+    Ok(items.permuted_axes(order))
+}
+
+/// Appends `size`-byte little endian elements.
+fn encode<I: Iterator<Item=u64>>(buffer: &mut Vec<u8>, values: I, size: usize) {
+    for x in values {
+        for k in 0..size {
+            buffer.push((x >> (8 * k)) as u8);
+        }
+    }
+}
+
+/// Narrows f32 to IEEE 754 half precision bits, dropping extra precision.
+/// HDF5 has no native half type, so f2 datasets are read as f32 and narrowed
+/// back, which is exact.
+fn f32_to_f16(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let frac = bits & 0x7fffff;
+    let e = exp - 127 + 15;
+    if exp == 0xff { sign | 0x7c00 | if frac != 0 { 0x200 } else { 0 } }
+    else if e >= 0x1f { sign | 0x7c00 }
+    else if e < -10 { sign }
+    else if e <= 0 { sign | ((frac | 0x800000) >> (14 - e)) as u16 }
+    else { sign | (e as u16) << 10 | (frac >> 13) as u16 }
+}
+
+impl SliceSource for H5File {
+    fn connect(&self) -> Result<String, FetchError> {
+        hdf5::File::open(&self.path).map_err(hdf5_error)?;
+        Ok(format!("HDF5 file {}", self.path.display()))
+    }
+
+    fn check(&self, _uri: &H5URI) -> Result<(), FetchError> {
+        Ok(())
+    }
+
+    fn fetch(&self, uri: &H5URI) -> Result<Vec<u8>, FetchError> {
+        // the file connected to, whatever path the URI names
+        let file = hdf5::File::open(&self.path).map_err(hdf5_error)?;
+        let dataset = file.dataset(&uri.h5path).map_err(hdf5_error)?;
+        let shape = dataset.shape();
+        if shape.is_empty() && uri.query != Query::All {
+            return Err(FetchError::UnsupportedQuery(uri.query.to_string()));
+        }
+        let query = &uri.query;
+        let mut buffer = Vec::new();
+        let size = uri.dtype.size();
+        match uri.dtype {
+            Dtype::I1 => encode(&mut buffer, read::<i8>(&dataset, query, &shape)?.iter().map(|x| *x as u8 as u64), size),
+            Dtype::U1 => encode(&mut buffer, read::<u8>(&dataset, query, &shape)?.iter().map(|x| *x as u64), size),
+            Dtype::I2 => encode(&mut buffer, read::<i16>(&dataset, query, &shape)?.iter().map(|x| *x as u16 as u64), size),
+            Dtype::U2 => encode(&mut buffer, read::<u16>(&dataset, query, &shape)?.iter().map(|x| *x as u64), size),
+            Dtype::I4 => encode(&mut buffer, read::<i32>(&dataset, query, &shape)?.iter().map(|x| *x as u32 as u64), size),
+            Dtype::F2 => encode(&mut buffer, read::<f32>(&dataset, query, &shape)?.iter().map(|x| f32_to_f16(*x) as u64), size),
+            Dtype::F4 => encode(&mut buffer, read::<f32>(&dataset, query, &shape)?.iter().map(|x| x.to_bits() as u64), size),
+            Dtype::F8 => encode(&mut buffer, read::<f64>(&dataset, query, &shape)?.iter().map(|x| x.to_bits()), size)
+        }
+        Ok(buffer)
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        ByteOrder::Little
+    }
+}
+
+impl H5Group {
+    /// Builds the group tree straight from an HDF5 file, in place of parsing
+    /// an `h5ls -r` listing of it.
+    pub fn read<P: AsRef<Path>>(fname: P) -> hdf5::Result<H5Obj> {
+        let file = hdf5::File::open(fname)?;
+        Ok(H5Obj::from(read_group(&file, "/")?))
+    }
+}
+
+fn read_group(group: &hdf5::Group, name: &str) -> hdf5::Result<H5Group> {
+    let mut ret = H5Group { name: String::from(name), children: BTreeMap::new() };
+    for member in group.member_names()? {
+        let child = if let Ok(g) = group.group(&member) {
+            H5Obj::from(read_group(&g, &member)?)
+        }
+        else if let Ok(d) = group.dataset(&member) {
+            H5Obj::from(read_dataset(&d, &member)?)
+        }
+        else {
+            // named datatypes, or soft and external links to nowhere
+            // TODO use logging instead
+            if cfg!(debug_assertions) { println!("Skipping {} in {}: neither a group nor a dataset", member, name); }
+            continue;
+        };
+        ret.children.insert(member, child);
+    }
+    Ok(ret)
+}
+
+fn read_dataset(dataset: &hdf5::Dataset, name: &str) -> hdf5::Result<H5Dataset> {
+    let mut ret = H5Dataset::new(String::from(name), dataset.shape());
+    let descriptor = dataset.dtype()?.to_descriptor();
+    ret.dtype = descriptor.as_ref().ok().and_then(descriptor_to_dtype);
+    ret.type_name = Some(match &descriptor {
+        Ok(descriptor) => format!("{:?}", descriptor),
+        Err(_) => String::from("unknown")
+    });
+    // HDF5 converts elements to native order on read
+    ret.byte_order = Some(ByteOrder::Little);
+    ret.chunks = dataset.chunks();
+    let filters = dataset.filters();
+    ret.compression =
+        if let Some(level) = filters.get_gzip() { Some(format!("deflate({})", level)) }
+        else if let Some((_, pixels)) = filters.get_szip() { Some(format!("szip({})", pixels)) }
+        else if filters.get_lzf() { Some(String::from("lzf")) }
+        else { None };
+    for attr_name in dataset.attr_names()? {
+        if let Some(value) = read_attr(&dataset.attr(&attr_name)?) {
+            ret.attrs.insert(attr_name, value);
+        }
+    }
+    Ok(ret)
+}
+
+fn descriptor_to_dtype(descriptor: &TypeDescriptor) -> Option<Dtype> {
+    let bytes = |size: &IntSize| match size {
+        IntSize::U1 => 1, IntSize::U2 => 2, IntSize::U4 => 4, IntSize::U8 => 8
+    };
+    match descriptor {
+        TypeDescriptor::Integer(size) => to_dtype("i", bytes(size) * 8),
+        TypeDescriptor::Unsigned(size) => to_dtype("u", bytes(size) * 8),
+        TypeDescriptor::Float(FloatSize::U4) => to_dtype("f", 32),
+        TypeDescriptor::Float(FloatSize::U8) => to_dtype("f", 64),
+        _ => None
+    }
+}
+
+/// Reads string and numeric attributes; anything else is left out.
+fn read_attr(attr: &hdf5::Attribute) -> Option<H5Attr> {
+    if let Ok(v) = attr.read_raw::<VarLenUnicode>() {
+        Some(H5Attr::Text(v.iter().map(|x| x.as_str().to_string()).collect()))
+    }
+    else if let Ok(v) = attr.read_raw::<VarLenAscii>() {
+        Some(H5Attr::Text(v.iter().map(|x| x.as_str().to_string()).collect()))
+    }
+    else if let Ok(v) = attr.read_raw::<f64>() {
+        Some(H5Attr::Number(v))
+    }
+    else { None }
+}
@@ -1,29 +1,219 @@
 extern crate image;
 extern crate flate2;
 
-use std::slice;
+use std;
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
-use std::net::TcpStream;
+use std::ops::Range;
+use std::net::{TcpStream, ToSocketAddrs};
 use flate2::read::GzDecoder;
+use std::str::FromStr;
 use std::string::ToString;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+use render;
+use render::{Normalization, ChannelMap, Colormap, min_max};
+use h5disk::DiskCache;
+
+/// Version of the slice server protocol spoken by this client.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Threads fetching slices in the background.
+pub const WORKERS: usize = 4;
+
+/// Longest wait for the slice server to accept a connection, and then for
+/// each read or write.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most pages fetched ahead while paging steadily in one direction.
+pub const MAX_LOOKAHEAD: usize = 3;
 
-// TODO cache f32 to decouple image pipeline
 pub type TexImage = image::RgbaImage;
 
+/// Decoded elements of one item, kept so display images can be derived again
+/// under different visual settings without refetching.
+pub struct Tensor {
+    /// (height, width, channels)
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>
+}
+
 #[allow(dead_code)]
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub enum Dtype {
-    I4, F4
+    I1, U1, I2, U2, I4, F2, F4, F8
 }
 
 impl ToString for Dtype {
+    fn to_string(&self) -> String {
+        String::from(match self {
+            Dtype::I1 => "i1",
+            Dtype::U1 => "u1",
+            Dtype::I2 => "i2",
+            Dtype::U2 => "u2",
+            Dtype::I4 => "i4",
+            Dtype::F2 => "f2",
+            Dtype::F4 => "f4",
+            Dtype::F8 => "f8"
+        })
+    }
+}
+
+impl Dtype {
+    /// Bytes per element on the wire.
+    pub fn size(&self) -> usize {
+        match self {
+            Dtype::I1 | Dtype::U1 => 1,
+            Dtype::I2 | Dtype::U2 | Dtype::F2 => 2,
+            Dtype::I4 | Dtype::F4 => 4,
+            Dtype::F8 => 8
+        }
+    }
+
+    /// Full value range of small integer types, which is mapped onto 0..255.
+    pub fn value_range(&self) -> Option<(f32, f32)> {
+        match self {
+            Dtype::I1 => Some((-128.0, 127.0)),
+            Dtype::U1 => Some((0.0, 255.0)),
+            Dtype::I2 => Some((-32768.0, 32767.0)),
+            Dtype::U2 => Some((0.0, 65535.0)),
+            _ => None
+        }
+    }
+}
+
+impl FromStr for Dtype {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dtype, String> {
+        match s {
+            "i1" => Ok(Dtype::I1),
+            "u1" => Ok(Dtype::U1),
+            "i2" => Ok(Dtype::I2),
+            "u2" => Ok(Dtype::U2),
+            "i4" => Ok(Dtype::I4),
+            "f2" => Ok(Dtype::F2),
+            "f4" => Ok(Dtype::F4),
+            "f8" => Ok(Dtype::F8),
+            _ => Err(format!("Unknown dtype: {}", s))
+        }
+    }
+}
+
+/// Byte order of multi-byte elements in a payload. The protocol defaults to
+/// little endian; servers advertising the `big-endian` capability send big endian.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ByteOrder {
+    Little, Big
+}
+
+impl ByteOrder {
+    /// Reads an unsigned integer as wide as `b`.
+    fn read_uint(&self, b: &[u8]) -> u64 {
+        let fold = |acc: u64, x: &u8| acc << 8 | *x as u64;
+        match self {
+            ByteOrder::Little => b.iter().rev().fold(0, fold),
+            ByteOrder::Big => b.iter().fold(0, fold)
+        }
+    }
+}
+
+/// Widens IEEE 754 half precision bits to f32.
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((h >> 10) & 0x1f) as i32;
+    let frac = (h & 0x3ff) as f32;
+    sign * match exp {
+        0 => frac * 2f32.powi(-24),
+        0x1f => if frac == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + frac / 1024.0) * 2f32.powi(exp - 15)
+    }
+}
+
+/// Decodes a whole number of elements into f32, reading byte by byte so the
+/// buffer needs no particular alignment.
+pub fn decode(bytes: &[u8], dtype: &Dtype, order: ByteOrder) -> Vec<f32> {
+    bytes.chunks(dtype.size()).map(|b| {
+        let x = order.read_uint(b);
+        match dtype {
+            Dtype::I1 => x as u8 as i8 as f32,
+            Dtype::U1 => x as u8 as f32,
+            Dtype::I2 => x as u16 as i16 as f32,
+            Dtype::U2 => x as u16 as f32,
+            Dtype::I4 => x as u32 as i32 as f32,
+            Dtype::F2 => f16_to_f32(x as u16),
+            Dtype::F4 => f32::from_bits(x as u32),
+            Dtype::F8 => f64::from_bits(x) as f32
+        }
+    }).collect()
+}
+
+/// Selection along one axis of a dataset.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum AxisSel {
+    /// A single position; the axis is dropped from the result.
+    Index(usize),
+    /// Every `step`-th position from `start` up to `end`, or to the end.
+    Range { start: usize, end: Option<usize>, step: usize },
+    All
+}
+
+impl AxisSel {
+    /// Positions selected on an axis of length `len`, or None if the axis is
+    /// dropped.
+    pub fn len(&self, len: usize) -> Option<usize> {
+        match *self {
+            AxisSel::Index(_) => None,
+            AxisSel::Range { start, end, step } => {
+                let end = std::cmp::min(end.unwrap_or(len), len);
+                Some(if end > start { (end - start + step - 1) / step } else { 0 })
+            },
+            AxisSel::All => Some(len)
+        }
+    }
+}
+
+/// Written like numpy: `5`, `2:8`, `::2`, `:`.
+impl ToString for AxisSel {
     fn to_string(&self) -> String {
         match self {
-            Dtype::I4 => String::from("i4"),
-            // Dtype::I8 => String::from("i8"),
-            Dtype::F4 => String::from("f4"),
-            // Dtype::F8 => String::from("f8"),
+            AxisSel::Index(idx) => idx.to_string(),
+            AxisSel::Range { start, end, step } => {
+                let end = end.map(|x| x.to_string()).unwrap_or(String::new());
+                if *step == 1 { format!("{}:{}", start, end) }
+                else { format!("{}:{}:{}", start, end, step) }
+            },
+            AxisSel::All => String::from(":")
+        }
+    }
+}
+
+impl FromStr for AxisSel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AxisSel, String> {
+        let invalid = || format!("Invalid axis selection \"{}\", expected INDEX or START:END:STEP", s);
+        let number = |x: &str, default: Option<usize>| if x.is_empty() { Ok(default) }
+            else { x.parse::<usize>().map(Some).map_err(|_| invalid()) };
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        match parts.len() {
+            1 => number(parts[0], None)?.map(AxisSel::Index).ok_or_else(invalid),
+            2 | 3 => {
+                let start = number(parts[0], Some(0))?.unwrap();
+                let end = number(parts[1], None)?;
+                let step = if parts.len() == 3 { number(parts[2], Some(1))?.unwrap() } else { 1 };
+                if step == 0 {
+                    return Err(invalid());
+                }
+                Ok(if start == 0 && end.is_none() && step == 1 { AxisSel::All }
+                    else { AxisSel::Range { start: start, end: end, step: step } })
+            },
+            _ => Err(invalid())
         }
     }
 }
@@ -33,15 +223,114 @@ impl ToString for Dtype {
 pub enum Query {
     One(usize),
     Range(usize, usize),
-    Batch(usize, usize)
+    Batch(usize, usize),
+    /// A hyperslab selected per axis. Items are taken along the paged `axis`,
+    /// whose selection is an `Index` or a unit step `Range`.
+    Slab { axis: usize, sel: Vec<AxisSel> },
+    /// The whole dataset as a single item.
+    All
+}
+
+impl Query {
+    /// Indices of the items selected along the batch axis.
+    pub fn indices(&self) -> Range<usize> {
+        match self {
+            Query::One(idx) => *idx..*idx+1,
+            Query::Range(a, b) => *a..*b,
+            Query::Batch(idx, len) => *idx..*idx+*len,
+            Query::All => 0..1,
+            Query::Slab { axis, sel } => match sel[*axis] {
+                AxisSel::Index(idx) => idx..idx+1,
+                AxisSel::Range { start, end, .. } => start..end.unwrap_or(start),
+                // the paged axis of a template, not yet applied to a dataset
+                AxisSel::All => 0..0
+            }
+        }
+    }
+
+    /// The query for item `idx` alone, with the same selection otherwise.
+    pub fn item(&self, idx: usize) -> Query {
+        match self {
+            Query::Slab { axis, sel } => {
+                let mut sel = sel.clone();
+                sel[*axis] = AxisSel::Index(idx);
+                Query::Slab { axis: *axis, sel: sel }
+            },
+            Query::All => Query::All,
+            _ => Query::One(idx)
+        }
+    }
+
+    /// The query for items `a..b`, with the same selection otherwise.
+    pub fn span(&self, a: usize, b: usize) -> Query {
+        match self {
+            Query::Slab { axis, sel } => {
+                let mut sel = sel.clone();
+                sel[*axis] = AxisSel::Range { start: a, end: Some(b), step: 1 };
+                Query::Slab { axis: *axis, sel: sel }
+            },
+            Query::All => Query::All,
+            _ => Query::Range(a, b)
+        }
+    }
+
+    /// Parses a slab template such as `*,5,:,:`, one selection per axis with
+    /// `*` marking the paged axis.
+    pub fn template(s: &str) -> Result<Query, String> {
+        let mut axis = None;
+        let mut sel = Vec::new();
+        for (k, part) in s.split(',').enumerate() {
+            if part.trim() == "*" {
+                if axis.is_some() {
+                    return Err(format!("More than one paged axis in \"{}\"", s));
+                }
+                axis = Some(k);
+                sel.push(AxisSel::All);
+            }
+            else {
+                sel.push(part.parse()?);
+            }
+        }
+        match axis {
+            Some(axis) => Ok(Query::Slab { axis: axis, sel: sel }),
+            None => Err(format!("No paged axis marked with * in \"{}\"", s))
+        }
+    }
+
+    /// A slab template applied to a dataset of `shape`: the query with the
+    /// paged axis spanning the whole dataset, and the shape of what it
+    /// selects, i.e. the paged axis, then the other axes that are not dropped.
+    /// None for other queries, or when the template does not fit.
+    pub fn apply_template(&self, shape: &[usize]) -> Option<(Query, Vec<usize>)> {
+        match self {
+            Query::Slab { axis, sel } if sel.len() == shape.len() => {
+                let mut ret = vec![shape[*axis]];
+                for (k, (s, &len)) in sel.iter().zip(shape).enumerate() {
+                    match s {
+                        AxisSel::Index(idx) if *idx >= len => return None,
+                        _ if k == *axis => {},
+                        _ => if let Some(n) = s.len(len) { ret.push(n); }
+                    }
+                }
+                Some((self.span(0, shape[*axis]), ret))
+            },
+            _ => None
+        }
+    }
 }
 
+/// Slab axes are joined by commas with the paged axis marked by a leading `*`;
+/// its items come first in the reply, each in row-major order.
 impl ToString for Query {
     fn to_string(&self) -> String {
         match self {
             Query::One(idx) => idx.to_string(),
             Query::Range(a, b) => format!("{}:{}", a, b),
-            Query::Batch(idx, len) => format!("{}:{}", idx, idx+len)
+            Query::Batch(idx, len) => format!("{}:{}", idx, idx+len),
+            Query::All => String::from(":"),
+            Query::Slab { axis, sel } => sel.iter().enumerate()
+                .map(|(k, s)| if k == *axis { format!("*{}", s.to_string()) } else { s.to_string() })
+                .collect::<Vec<String>>().join(",")
         }
     }
 }
@@ -54,113 +343,1027 @@ pub struct H5URI {
     pub dtype: Dtype
 }
 
+impl H5URI {
+    /// (file, dataset) the items belong to.
+    pub fn dataset(&self) -> (String, String) {
+        (self.path.clone(), self.h5path.clone())
+    }
+}
+
 impl ToString for H5URI {
     fn to_string(&self) -> String {
         [self.path.clone(), self.h5path.clone(), self.query.to_string(), self.dtype.to_string()].join("\t")
     }
 }
 
-pub struct H5Cache {
-    buffer: HashMap<H5URI, TexImage>,
-    hint: usize
+/// Why a slice could not be fetched or decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    /// Nothing is listening at the slice server endpoint.
+    ConnectionRefused(String),
+    /// Any other socket failure while talking to the slice server.
+    Network(String),
+    /// The server has not been (successfully) handshaked with.
+    NotConnected(String),
+    /// The server speaks another protocol version or replied with garbage.
+    Incompatible(String),
+    /// The payload holds fewer bytes than the requested items need.
+    ShortRead { expected: usize, actual: usize },
+    /// The payload is not gzip or the stream is truncated.
+    Decompression(String),
+    /// The payload size is not a whole number of items of the expected shape.
+    SizeMismatch { expected: usize, actual: usize },
+    /// The dtype is not supported by the server or by the decoder.
+    UnknownDtype(String),
+    /// The server answered with an error message of its own.
+    Server(String),
+    /// The query kind cannot be requested this way.
+    UnsupportedQuery(String),
+    /// The item lies past the end of the dataset.
+    OutOfRange { index: usize, len: usize },
+    /// A local backend failed to read the file.
+    #[allow(dead_code)]
+    Source(String)
 }
 
-impl H5Cache {
-    pub fn new() -> H5Cache {
-        H5Cache { buffer: HashMap::with_capacity(60), hint: 32 }
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::ConnectionRefused(endpoint) =>
+                write!(f, "Connection refused by slice server {}", endpoint),
+            FetchError::Network(msg) => write!(f, "Network error: {}", msg),
+            FetchError::NotConnected(endpoint) =>
+                write!(f, "Not connected to a compatible slice server at {}", endpoint),
+            FetchError::Incompatible(msg) => write!(f, "Incompatible slice server: {}", msg),
+            FetchError::ShortRead { expected, actual } =>
+                write!(f, "Short read: expected {} bytes, got {}", expected, actual),
+            FetchError::Decompression(msg) => write!(f, "Decompression failed: {}", msg),
+            FetchError::SizeMismatch { expected, actual } =>
+                write!(f, "Size mismatch: {} bytes is not a multiple of {} bytes per image", actual, expected),
+            FetchError::UnknownDtype(dtype) => write!(f, "Unsupported dtype: {}", dtype),
+            FetchError::Server(msg) => write!(f, "Server error: {}", msg),
+            FetchError::UnsupportedQuery(query) => write!(f, "Unsupported query: [{}]", query),
+            FetchError::OutOfRange { index, len } =>
+                write!(f, "Item {} is out of range, the dataset holds {}", index, len),
+            FetchError::Source(msg) => write!(f, "Read error: {}", msg)
+        }
     }
+}
 
-    pub fn request(&mut self, uri: &H5URI, resolution: (u32, u32)) -> Option<&'_ mut TexImage> {
-        match uri.query {
-            Query::One(_) =>
-                if self.buffer.contains_key(uri) {
-                    Some(self.buffer.get_mut(uri).unwrap())
-                }
-                else {
-                    self.fetch_one(uri, resolution)
+impl FetchError {
+    fn from_io(endpoint: &str, e: io::Error) -> FetchError {
+        match e.kind() {
+            io::ErrorKind::ConnectionRefused => FetchError::ConnectionRefused(String::from(endpoint)),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => FetchError::Network(format!("{}: timed out", endpoint)),
+            _ => FetchError::Network(format!("{}: {}", endpoint, e))
+        }
+    }
+}
+
+/// What the slice server reported about itself during the handshake.
+pub struct ServerInfo {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+    pub dtypes: Vec<Dtype>
+}
+
+impl ServerInfo {
+    /// Parses the handshake reply: `version \t capability,... \t dtype,...`
+    fn parse(reply: &str) -> Result<ServerInfo, FetchError> {
+        let malformed = || FetchError::Incompatible(format!("Malformed handshake reply: {:?}", reply));
+        let fields: Vec<&str> = reply.trim().split('\t').collect();
+        if fields.len() != 3 {
+            return Err(malformed());
+        }
+        let version = fields[0].parse().map_err(|_| malformed())?;
+        let list = |s: &str| s.split(',').filter(|x| !x.is_empty()).map(String::from).collect::<Vec<String>>();
+        Ok(ServerInfo {
+            version: version,
+            capabilities: list(fields[1]),
+            // dtypes unknown to this client are simply never requested
+            dtypes: list(fields[2]).iter().filter_map(|x| x.parse().ok()).collect()
+        })
+    }
+
+    pub fn supports(&self, dtype: &Dtype) -> bool {
+        self.dtypes.contains(dtype)
+    }
+
+    /// Whether slab queries may be sent, their reply format being newer than
+    /// the protocol version.
+    pub fn supports_slabs(&self) -> bool {
+        self.capabilities.iter().any(|x| x == "slab")
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        if self.capabilities.iter().any(|x| x == "big-endian") { ByteOrder::Big }
+        else { ByteOrder::Little }
+    }
+}
+
+/// A backend producing slice payloads: the items selected by `uri.query`
+/// concatenated in row-major order, elements encoded as `uri.dtype` in
+/// `byte_order()`. Fetches and `connect` run on worker threads, concurrently
+/// with each other.
+pub trait SliceSource: Send + Sync {
+    /// Prepares the backend and describes it for the status bar.
+    fn connect(&self) -> Result<String, FetchError>;
+
+    /// Explains why `uri` cannot be served, if it cannot.
+    fn check(&self, uri: &H5URI) -> Result<(), FetchError>;
+
+    fn fetch(&self, uri: &H5URI) -> Result<Vec<u8>, FetchError>;
+
+    fn byte_order(&self) -> ByteOrder;
+}
+
+/// The external slice server speaking the tab-separated H5URI protocol.
+pub struct SliceServer {
+    endpoint: String,
+    /// Set by a successful handshake. Only held to read or replace it, never
+    /// during network I/O.
+    server: RwLock<Option<ServerInfo>>
+}
+
+impl SliceServer {
+    pub fn new(endpoint: &str) -> SliceServer {
+        SliceServer { endpoint: String::from(endpoint), server: RwLock::new(None) }
+    }
+
+    /// Connects to the endpoint, giving up on a server that takes longer than
+    /// `CONNECT_TIMEOUT` to accept or `IO_TIMEOUT` between reads and writes.
+    fn open(&self) -> Result<TcpStream, FetchError> {
+        let io_error = |e| FetchError::from_io(&self.endpoint, e);
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address");
+        for addr in self.endpoint.to_socket_addrs().map_err(io_error)? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(io_error)?;
+                    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(io_error)?;
+                    return Ok(stream);
                 },
-            _ => None
+                Err(e) => last_error = e
+            }
+        }
+        Err(io_error(last_error))
+    }
+
+    /// Exchanges protocol versions with the slice server. Data is only requested
+    /// after a successful handshake with a server speaking `PROTOCOL_VERSION`.
+    pub fn handshake(&self) -> Result<ServerInfo, FetchError> {
+        *self.server.write().unwrap() = None;
+        let mut stream = self.open()?;
+        stream.write_all(format!("HELLO\t{}", PROTOCOL_VERSION).as_bytes())
+            .map_err(|e| FetchError::from_io(&self.endpoint, e))?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)
+            .map_err(|e| FetchError::from_io(&self.endpoint, e))?;
+        let info = ServerInfo::parse(&reply)?;
+        if info.version != PROTOCOL_VERSION {
+            return Err(FetchError::Incompatible(format!("{} speaks protocol v{}, expected v{}",
+                self.endpoint, info.version, PROTOCOL_VERSION)));
         }
+        Ok(info)
+    }
+}
+
+impl SliceSource for SliceServer {
+    fn connect(&self) -> Result<String, FetchError> {
+        let info = self.handshake()?;
+        let description = format!("Slice server {} v{} [{}]", self.endpoint, info.version,
+            info.dtypes.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", "));
+        *self.server.write().unwrap() = Some(info);
+        Ok(description)
     }
 
-    fn download(uri: &H5URI) -> Option<Vec<u8>> {
-        let mut stream = TcpStream::connect("localhost:8000").ok()?;
+    fn check(&self, uri: &H5URI) -> Result<(), FetchError> {
+        match &*self.server.read().unwrap() {
+            None => Err(FetchError::NotConnected(self.endpoint.clone())),
+            Some(info) if !info.supports(&uri.dtype) =>
+                Err(FetchError::UnknownDtype(uri.dtype.to_string())),
+            Some(info) => match uri.query {
+                Query::Slab { .. } if !info.supports_slabs() =>
+                    Err(FetchError::UnsupportedQuery(uri.query.to_string())),
+                _ => Ok(())
+            }
+        }
+    }
+
+    fn fetch(&self, uri: &H5URI) -> Result<Vec<u8>, FetchError> {
+        self.check(uri)?;
+        let mut stream = self.open()?;
         let mut buffer_in = Vec::with_capacity(8<<20);
         let mut buffer_out = Vec::with_capacity(20<<20);
-        let _ = stream.write(uri.to_string().as_bytes());
-        let n = stream.read_to_end(&mut buffer_in).ok()?;
+        stream.write_all(uri.to_string().as_bytes())
+            .map_err(|e| FetchError::from_io(&self.endpoint, e))?;
+        let n = stream.read_to_end(&mut buffer_in)
+            .map_err(|e| FetchError::from_io(&self.endpoint, e))?;
         // TODO use logging instead
         if cfg!(debug_assertions) { println!("Read {} bytes from network.", n); }
+        // errors are sent uncompressed as "ERR\t<message>"
+        if buffer_in.starts_with(b"ERR\t") {
+            return Err(FetchError::Server(String::from_utf8_lossy(&buffer_in[4..]).trim().to_string()));
+        }
         let mut decoder = GzDecoder::new(&buffer_in[..]);
-        let n = decoder.read_to_end(&mut buffer_out).ok()?;
+        let n = decoder.read_to_end(&mut buffer_out)
+            .map_err(|e| FetchError::Decompression(e.to_string()))?;
         if cfg!(debug_assertions) { println!("Decompressed into {} bytes.", n); }
-        return Some(buffer_out);
+        return Ok(buffer_out);
     }
 
-    fn deserialize(buffer: &Vec<u8>, resolution: &(u32, u32), im_offset: isize) -> Option<TexImage> {
-        let im_size = (resolution.0*resolution.1*3) as usize;
-        let data = unsafe {
-            let base = buffer.as_ptr() as *const f32;
-            slice::from_raw_parts(base.offset(im_offset * (im_size as isize)), im_size)
-        };
-        Some(image::DynamicImage::ImageRgb8(image::ImageBuffer::from_raw(
-            resolution.0, resolution.1,
-            data.into_iter().map(|x| {
-                (x+100.0) as u8
-            }
-        ).collect())?).to_rgba())
+    fn byte_order(&self) -> ByteOrder {
+        self.server.read().unwrap().as_ref().map(|info| info.byte_order()).unwrap_or(ByteOrder::Little)
     }
+}
+
+/// Dimensions of one item and the order of its elements.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct ItemShape {
+    pub height: usize,
+    pub width: usize,
+    pub channels: usize,
+    /// Elements ordered (channels, height, width) instead of (height, width, channels).
+    pub channels_first: bool
+}
+
+impl ItemShape {
+    /// Elements per item.
+    pub fn size(&self) -> usize {
+        self.height * self.width * self.channels
+    }
+}
+
+impl fmt::Display for ItemShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.channels_first { write!(f, "{}x{}x{}", self.channels, self.height, self.width) }
+        else { write!(f, "{}x{}x{}", self.height, self.width, self.channels) }
+    }
+}
+
+impl Tensor {
+    /// Bytes of memory held by the elements.
+    pub fn bytes(&self) -> usize {
+        self.data.len() * std::mem::size_of::<f32>()
+    }
+}
+
+/// Cache effectiveness counters.
+#[derive(Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    /// Bytes held by cached tensors and images.
+    pub used: usize,
+    pub budget: usize
+}
 
-    pub fn prefetch(&mut self, uri: &H5URI, resolution: (u32, u32)) {
-        if let Some(buffer_out) = H5Cache::download(uri) {
-            match uri.query {
-                Query::One(_) => {
-                    if let Some(im_rgba) = H5Cache::deserialize(&buffer_out, &resolution, 0) {
-                        self.buffer.insert(uri.clone(), im_rgba);
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let requests = self.hits + self.misses;
+        let hit_rate = if requests > 0 { 100 * self.hits / requests } else { 0 };
+        write!(f, "cache {}/{}MB hit {}% evicted {}",
+            self.used >> 20, self.budget >> 20, hit_rate, self.evictions)
+    }
+}
+
+pub struct H5Cache {
+    tensors: HashMap<H5URI, Tensor>,
+    /// Display images derived from `tensors` under the current settings.
+    images: HashMap<H5URI, TexImage>,
+    /// Last use of each cached item, and the items by last use.
+    stamps: HashMap<H5URI, u64>,
+    lru: BTreeMap<u64, H5URI>,
+    clock: u64,
+    /// Items never evicted, i.e. the page on screen.
+    pinned: HashSet<H5URI>,
+    stats: CacheStats,
+    source: Arc<Box<dyn SliceSource>>,
+    /// Queue of the worker threads, and their results.
+    jobs: Sender<Job>,
+    done: Receiver<Done>,
+    /// Connections finished so far. Fetches queued before the last one may
+    /// have failed for want of it, so their errors are not kept.
+    epoch: usize,
+    /// Items being fetched, and items whose fetch failed.
+    pending: HashSet<H5URI>,
+    failed: HashMap<H5URI, FetchError>,
+    normalization: Normalization,
+    channel_map: ChannelMap,
+    colormap: Colormap,
+    /// Min..max seen per (file, dataset), for `Normalization::DatasetMinMax`.
+    ranges: HashMap<(String, String), (f32, f32)>,
+    /// Items per (file, dataset), where known.
+    lengths: HashMap<(String, String), usize>,
+    /// Item shape per (file, dataset) as last requested.
+    shapes: HashMap<(String, String), ItemShape>,
+    paging: Option<Paging>
+}
+
+/// Recent page turns in one dataset, to guess the next ones.
+struct Paging {
+    dataset: (String, String),
+    start: usize,
+    forward: bool,
+    /// Page turns in a row in the `forward` direction.
+    streak: usize
+}
+
+/// Background work: a fetch of `uri` queued in connection `epoch`, or a
+/// (re)connection of the source.
+enum Job {
+    Fetch { uri: H5URI, item: ItemShape, epoch: usize },
+    Connect
+}
+
+/// A finished `Job` with its decoded payload, or the description of the
+/// connected source.
+enum Done {
+    Fetch { uri: H5URI, item: ItemShape, epoch: usize, result: Result<Vec<f32>, FetchError> },
+    Connect(Result<String, FetchError>)
+}
+
+/// What finished since the last `poll`.
+pub struct Polled {
+    /// Items fetched or failed, i.e. whose requests may now have other answers.
+    pub items: Vec<H5URI>,
+    /// Outcome of the last connection, if one finished.
+    pub connected: Option<Result<String, FetchError>>
+}
+
+/// Starts `n` threads fetching and decoding jobs, each item from `disk` if
+/// there, the rest from `source` and then saved to `disk`. They stop once the
+/// returned sender or receiver is dropped.
+fn spawn_workers(source: &Arc<Box<dyn SliceSource>>, disk: Option<Arc<DiskCache>>, n: usize) -> (Sender<Job>, Receiver<Done>) {
+    let (jobs, job_queue) = channel::<Job>();
+    let (done_queue, done) = channel::<Done>();
+    let job_queue = Arc::new(Mutex::new(job_queue));
+    for _ in 0..n {
+        let (source, disk, job_queue, done_queue) = (source.clone(), disk.clone(), job_queue.clone(), done_queue.clone());
+        thread::spawn(move || loop {
+            let job = job_queue.lock().unwrap().recv();
+            let (uri, item, epoch) = match job {
+                Ok(Job::Fetch { uri, item, epoch }) => (uri, item, epoch),
+                Ok(Job::Connect) => {
+                    if done_queue.send(Done::Connect(source.connect())).is_err() {
+                        break;
                     }
+                    continue;
                 },
-                Query::Batch(idx, len) => {
-                    let mut uri_one = uri.clone();
-                    for (offset, i) in (idx..idx+len).enumerate() {
-                        if let Some(im_rgba) = H5Cache::deserialize(&buffer_out, &resolution, offset as isize) {
-                            uri_one.query = Query::One(i);
-                            self.buffer.insert(uri_one.clone(), im_rgba);
-                        }
-                    }
+                Err(_) => break
+            };
+            for (uri, result) in fetch_runs(&**source, disk.as_ref().map(|disk| &**disk), &uri, &item) {
+                if done_queue.send(Done::Fetch { uri: uri, item: item, epoch: epoch, result: result }).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    (jobs, done)
+}
+
+/// Fetches the items selected by `uri` in runs of neighbours, taking those
+/// on `disk` from there and the rest from `source`. Returns each run's query
+/// with its elements.
+fn fetch_runs(source: &dyn SliceSource, disk: Option<&DiskCache>, uri: &H5URI, item: &ItemShape) -> Vec<(H5URI, Result<Vec<f32>, FetchError>)> {
+    let indices = uri.query.indices();
+    let mut uri_one = uri.clone();
+    let cached: Vec<Option<Vec<f32>>> = indices.clone().map(|i| {
+        uri_one.query = uri.query.item(i);
+        disk.and_then(|disk| disk.load(&uri_one, item))
+    }).collect();
+    let mut runs = Vec::new();
+    let mut start = indices.start;
+    while start < indices.end {
+        let on_disk = cached[start - indices.start].is_some();
+        let mut end = start + 1;
+        while end < indices.end && cached[end - indices.start].is_some() == on_disk {
+            end += 1;
+        }
+        let run = H5URI { query: uri.query.span(start, end), ..uri.clone() };
+        let result = if on_disk {
+            Ok(cached[start - indices.start..end - indices.start].iter().flat_map(|data| data.clone().unwrap()).collect())
+        }
+        else {
+            let result = source.fetch(&run).and_then(|buffer|
+                decode_payload(&buffer, &run.dtype, item, source.byte_order()));
+            if let (Some(disk), Ok(data)) = (disk, &result) {
+                if let Err(e) = disk.store_many(&run, item, data) {
+                    // TODO use logging instead
+                    if cfg!(debug_assertions) { println!("Cannot write disk cache: {}", e); }
+                }
+            }
+            result
+        };
+        runs.push((run, result));
+        start = end;
+    }
+    runs
+}
+
+/// Decodes a payload holding a whole number of items.
+fn decode_payload(buffer: &[u8], dtype: &Dtype, item: &ItemShape, order: ByteOrder) -> Result<Vec<f32>, FetchError> {
+    let im_bytes = item.size() * dtype.size();
+    if im_bytes == 0 || buffer.len() % im_bytes != 0 {
+        return Err(FetchError::SizeMismatch { expected: im_bytes, actual: buffer.len() });
+    }
+    Ok(decode(buffer, dtype, order))
+}
+
+impl H5Cache {
+    /// Creates a cache holding at most about `budget` bytes of tensors and
+    /// images fetched from `source` by `WORKERS` background threads, backed by
+    /// `disk` if given.
+    pub fn new(source: Box<dyn SliceSource>, budget: usize, disk: Option<DiskCache>) -> H5Cache {
+        let source = Arc::new(source);
+        let (jobs, done) = spawn_workers(&source, disk.map(Arc::new), WORKERS);
+        H5Cache {
+            tensors: HashMap::with_capacity(60),
+            images: HashMap::with_capacity(60),
+            stamps: HashMap::with_capacity(60),
+            lru: BTreeMap::new(),
+            clock: 0,
+            pinned: HashSet::new(),
+            stats: CacheStats { budget: budget, ..CacheStats::default() },
+            source: source,
+            jobs: jobs,
+            done: done,
+            epoch: 0,
+            pending: HashSet::new(),
+            failed: HashMap::new(),
+            normalization: Normalization::ImageMinMax,
+            channel_map: ChannelMap::Rgb(0, 1, 2),
+            colormap: Colormap::Gray,
+            ranges: HashMap::new(),
+            lengths: HashMap::new(),
+            shapes: HashMap::new(),
+            paging: None
+        }
+    }
+
+    /// Elements of the single item `uri`, if cached.
+    pub fn tensor(&self, uri: &H5URI) -> Option<&Tensor> {
+        self.tensors.get(uri)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Keeps exactly `uris` from being evicted, e.g. the page on screen.
+    pub fn pin<I: IntoIterator<Item=H5URI>>(&mut self, uris: I) {
+        self.pinned = uris.into_iter().collect();
+    }
+
+    fn touch(&mut self, uri: &H5URI) {
+        self.clock += 1;
+        if let Some(stamp) = self.stamps.insert(uri.clone(), self.clock) {
+            self.lru.remove(&stamp);
+        }
+        self.lru.insert(self.clock, uri.clone());
+    }
+
+    fn insert_tensor(&mut self, uri: H5URI, tensor: Tensor) {
+        self.stats.used += tensor.bytes();
+        self.touch(&uri);
+        if let Some(old) = self.tensors.insert(uri, tensor) {
+            self.stats.used -= old.bytes();
+        }
+    }
+
+    fn remove(&mut self, uri: &H5URI) {
+        if let Some(tensor) = self.tensors.remove(uri) {
+            self.stats.used -= tensor.bytes();
+        }
+        if let Some(im) = self.images.remove(uri) {
+            self.stats.used -= im.len();
+        }
+        if let Some(stamp) = self.stamps.remove(uri) {
+            self.lru.remove(&stamp);
+        }
+    }
+
+    /// Drops least recently used items until the budget is met, sparing
+    /// pinned items and `keep`.
+    fn evict(&mut self, keep: &[H5URI]) {
+        while self.stats.used > self.stats.budget {
+            let victim = self.lru.values()
+                .find(|uri| !keep.contains(uri) && !self.pinned.contains(*uri))
+                .cloned();
+            match victim {
+                Some(uri) => {
+                    self.remove(&uri);
+                    self.stats.evictions += 1;
                 },
-                Query::Range(a, b) => {
-                    let mut uri_one = uri.clone();
-                    for (offset, i) in (a..b).enumerate() {
-                        if let Some(im_rgba) = H5Cache::deserialize(&buffer_out, &resolution, offset as isize) {
-                            uri_one.query = Query::One(i);
-                            self.buffer.insert(uri_one.clone(), im_rgba);
-                        }
-                    }
+                None => break
+            }
+        }
+    }
+
+    /// (Re)connects the source in the background, `poll` telling how it
+    /// went. Failed items are fetched again on their next request after.
+    pub fn connect(&mut self) {
+        // the workers live as long as the cache, so sending cannot fail
+        self.jobs.send(Job::Connect).unwrap();
+    }
+
+    pub fn check(&self, uri: &H5URI) -> Result<(), FetchError> {
+        self.source.check(uri)
+    }
+
+    /// Records that the dataset of `uri` holds `len` items, e.g. from its
+    /// shape, so that no request reaches past its end.
+    pub fn set_length(&mut self, uri: &H5URI, len: usize) {
+        self.lengths.insert(uri.dataset(), len);
+    }
+
+    /// Drops everything cached from the dataset of `uri` once its items are
+    /// read as shaped otherwise, e.g. under another layout.
+    fn reshape(&mut self, uri: &H5URI, item: ItemShape) {
+        let dataset = uri.dataset();
+        if self.shapes.insert(dataset.clone(), item).map(|x| x == item).unwrap_or(true) {
+            return;
+        }
+        let stale: Vec<H5URI> = self.stamps.keys().filter(|u| u.dataset() == dataset).cloned().collect();
+        for u in stale {
+            self.remove(&u);
+        }
+        self.failed.retain(|u, _| u.dataset() != dataset);
+        self.ranges.remove(&dataset);
+    }
+
+    /// `indices` cut to the length of the dataset of `uri`, if known.
+    fn clamp(&self, uri: &H5URI, indices: Range<usize>) -> Range<usize> {
+        match self.lengths.get(&uri.dataset()) {
+            Some(&len) => std::cmp::min(indices.start, len)..std::cmp::min(indices.end, len),
+            None => indices
+        }
+    }
+
+    pub fn normalization(&self) -> &Normalization {
+        &self.normalization
+    }
+
+    /// Changes how values map to pixels. Cached data is kept, only the
+    /// display images are derived again.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        if normalization != self.normalization {
+            self.normalization = normalization;
+            self.clear_images();
+        }
+    }
+
+    pub fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    /// Changes which channels are shown, like `set_normalization`.
+    pub fn set_channel_map(&mut self, channel_map: ChannelMap) {
+        if channel_map != self.channel_map {
+            self.channel_map = channel_map;
+            self.clear_images();
+        }
+    }
+
+    pub fn colormap(&self) -> &Colormap {
+        &self.colormap
+    }
+
+    /// Changes how single channels are colored, like `set_normalization`.
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        if colormap != self.colormap {
+            self.colormap = colormap;
+            self.clear_images();
+        }
+    }
+
+    /// Values at both ends of the colormap when they are the same for every
+    /// image of the dataset of `uri`, for a legend.
+    pub fn legend(&self, uri: &H5URI) -> Option<(f32, f32)> {
+        let range = self.ranges.get(&uri.dataset()).cloned();
+        self.normalization.bounds(&[], &uri.dtype, range).map(|bounds| self.colormap.center(bounds))
+    }
+
+    fn clear_images(&mut self) {
+        for (_, im) in self.images.drain() {
+            self.stats.used -= im.len();
+        }
+    }
+
+    /// Display images of the items selected by `uri`, in order: each one
+    /// ready, None while fetched in the background (`poll` tells when to ask
+    /// again), or why it is missing. Only missing items get fetched.
+    pub fn request(&mut self, uri: &H5URI, item: ItemShape) -> Vec<Result<Option<&TexImage>, FetchError>> {
+        self.reshape(uri, item);
+        self.fetch_missing(uri, uri.query.indices(), item);
+        let mut uri_one = uri.clone();
+        let uris: Vec<H5URI> = uri.query.indices().map(|i| { uri_one.query = uri.query.item(i); uri_one.clone() }).collect();
+        let ready: Vec<Result<bool, FetchError>> = uris.iter().map(|u| self.prepare(u)).collect();
+        self.evict(&uris);
+        let images = &self.images;
+        uris.iter().zip(ready).map(|(u, ready)| ready.map(|ready|
+            if ready { images.get(u) } else { None })).collect()
+    }
+
+    /// Elements of the items selected by `uri`, like `request` but without
+    /// deriving display images, e.g. for plots and labels.
+    pub fn request_tensors(&mut self, uri: &H5URI, item: ItemShape) -> Vec<Result<Option<&Tensor>, FetchError>> {
+        self.reshape(uri, item);
+        self.fetch_missing(uri, uri.query.indices(), item);
+        let mut uri_one = uri.clone();
+        let uris: Vec<H5URI> = uri.query.indices().map(|i| { uri_one.query = uri.query.item(i); uri_one.clone() }).collect();
+        let ready: Vec<Result<bool, FetchError>> = uris.iter().map(|u| self.arrived(u)).collect();
+        self.evict(&uris);
+        let tensors = &self.tensors;
+        uris.iter().zip(ready).map(|(u, ready)| ready.map(|ready|
+            if ready { tensors.get(u) } else { None })).collect()
+    }
+
+    /// Whether the data of item `uri` is there, marking it used if so.
+    fn arrived(&mut self, uri: &H5URI) -> Result<bool, FetchError> {
+        let idx = uri.query.indices().start;
+        if let Some(&len) = self.lengths.get(&uri.dataset()) {
+            if idx >= len {
+                return Err(FetchError::OutOfRange { index: idx, len: len });
+            }
+        }
+        if let Some(e) = self.failed.get(uri) {
+            return Err(e.clone());
+        }
+        if !self.tensors.contains_key(uri) {
+            return Ok(false);
+        }
+        self.touch(uri);
+        Ok(true)
+    }
+
+    /// Derives the display image of item `uri` if its data is there. Returns
+    /// whether the image is ready.
+    fn prepare(&mut self, uri: &H5URI) -> Result<bool, FetchError> {
+        if !self.arrived(uri)? {
+            return Ok(false);
+        }
+        if !self.images.contains_key(uri) {
+            let range = self.ranges.get(&uri.dataset()).cloned();
+            let im_rgba = render::to_image(&self.tensors[uri], &uri.dtype, &self.channel_map, &self.colormap, &self.normalization, range);
+            self.stats.used += im_rgba.len();
+            self.images.insert(uri.clone(), im_rgba);
+        }
+        Ok(true)
+    }
+
+    /// Cuts the `im_offset`-th item out of decoded payload `data`, with the
+    /// channels last.
+    fn deserialize(uri: &H5URI, data: &[f32], item: &ItemShape, im_offset: usize) -> Result<Tensor, FetchError> {
+        let im_size = item.size();
+        let (begin, end) = (im_offset * im_size, (im_offset + 1) * im_size);
+        if data.len() < end {
+            let size = uri.dtype.size();
+            return Err(FetchError::ShortRead { expected: end * size, actual: data.len() * size });
+        }
+        let src = &data[begin..end];
+        let (height, width, channels) = (item.height, item.width, item.channels);
+        let data = if item.channels_first {
+            let plane = height * width;
+            (0..im_size).map(|i| src[(i % channels) * plane + i / channels]).collect()
+        }
+        else { src.to_vec() };
+        Ok(Tensor { shape: vec![height, width, channels], data: data })
+    }
+
+    fn update_range(&mut self, uri: &H5URI, data: &[f32]) {
+        if let Some((low, high)) = min_max(data) {
+            let range = self.ranges.entry(uri.dataset()).or_insert((low, high));
+            *range = (range.0.min(low), range.1.max(high));
+        }
+    }
+
+    /// Queues a background fetch of the items selected by `uri`, as far as
+    /// the dataset reaches.
+    pub fn prefetch(&mut self, uri: &H5URI, item: ItemShape) {
+        let indices = self.clamp(uri, uri.query.indices());
+        if indices.start >= indices.end {
+            return;
+        }
+        let uri = &H5URI { query: uri.query.span(indices.start, indices.end), ..uri.clone() };
+        let mut uri_one = uri.clone();
+        for i in uri.query.indices() {
+            uri_one.query = uri.query.item(i);
+            self.pending.insert(uri_one.clone());
+        }
+        // the workers live as long as the cache, so sending cannot fail
+        self.jobs.send(Job::Fetch { uri: uri.clone(), item: item, epoch: self.epoch }).unwrap();
+    }
+
+    /// Stores the results of finished background fetches and connections.
+    pub fn poll(&mut self) -> Polled {
+        let mut polled = Polled { items: Vec::new(), connected: None };
+        while let Ok(done) = self.done.try_recv() {
+            let (uri, item, epoch, result) = match done {
+                Done::Fetch { uri, item, epoch, result } => (uri, item, epoch, result),
+                Done::Connect(result) => {
+                    // whatever failed before may work now
+                    self.epoch += 1;
+                    self.failed.clear();
+                    polled.connected = Some(result);
+                    continue;
                 }
+            };
+            let mut uri_one = uri.clone();
+            for i in uri.query.indices() {
+                uri_one.query = uri.query.item(i);
+                self.pending.remove(&uri_one);
+                polled.items.push(uri_one.clone());
             }
+            if self.shapes.get(&uri.dataset()) != Some(&item) {
+                // read under a layout since changed, to be requested again
+                continue;
+            }
+            match result {
+                Ok(data) => {
+                    self.update_range(&uri, &data);
+                    self.insert_many(&uri, uri.query.indices(), &data, &item);
+                },
+                // queued before the last connection finished, to be requested again
+                Err(_) if epoch < self.epoch => (),
+                Err(e) => for i in uri.query.indices() {
+                    uri_one.query = uri.query.item(i);
+                    self.failed.insert(uri_one.clone(), e.clone());
+                }
+            }
+        }
+        if !polled.items.is_empty() {
+            self.evict(&[]);
         }
+        polled
     }
 
-    fn auto_prefetch_uri(&self, uri_original: &H5URI) -> H5URI {
-        let mut uri = uri_original.clone();
-        match uri.query {
-            Query::One(idx) => {
-                // ? check overlap?
-                uri.query = Query::Batch(idx, self.hint);
-            },
-            _ => {}
+    /// Splits a multi-item reply into the cache. Items a short reply lacks
+    /// are marked failed rather than fetched again and again.
+    fn insert_many(&mut self, uri: &H5URI, indices: Range<usize>, data: &[f32], item: &ItemShape) {
+        let mut uri_one = uri.clone();
+        for (offset, i) in indices.enumerate() {
+            uri_one.query = uri.query.item(i);
+            match H5Cache::deserialize(uri, data, item, offset) {
+                Ok(tensor) => self.insert_tensor(uri_one.clone(), tensor),
+                Err(e) => { self.failed.insert(uri_one.clone(), e); }
+            }
         }
-        return uri;
     }
 
-    fn fetch_one(&mut self, uri: &H5URI, resolution: (u32, u32)) -> Option<&'_ mut TexImage> {
-        match uri.query {
-            Query::One(_) => {
-                let uri_prefetch = self.auto_prefetch_uri(uri);
-                self.prefetch(&uri_prefetch, resolution);
-                Some(self.buffer.get_mut(uri).unwrap())
-            },
-            _ => unreachable!() // uri is ensured to be One because this function is private!
+    /// Announces that items `page`, out of pages of `page_size` items, of the
+    /// dataset of `uri` are on screen, along with item `attached` of another
+    /// dataset if given, e.g. the labels of the page.
+    /// Pins them, fetches whichever are missing, and prefetches the pages
+    /// likely to be turned to next: up to `MAX_LOOKAHEAD` pages in the
+    /// paging direction, more the longer it has been kept, and one page back.
+    pub fn view(&mut self, uri: &H5URI, page: Range<usize>, page_size: usize, item: ItemShape, attached: Option<&H5URI>) {
+        self.reshape(uri, item);
+        let mut uri_one = uri.clone();
+        let page_uris: Vec<H5URI> = page.clone().map(|i| { uri_one.query = uri.query.item(i); uri_one.clone() }).collect();
+        let dataset = uri.dataset();
+        let fresh = self.paging.as_ref().map(|p| p.dataset != dataset).unwrap_or(true);
+        if fresh {
+            self.paging = Some(Paging { dataset: dataset, start: page.start, forward: true, streak: 0 });
+        }
+        let (turned, forward, streak) = {
+            let paging = self.paging.as_mut().unwrap();
+            let turned = page.start != paging.start;
+            if turned {
+                let forward = page.start > paging.start;
+                paging.streak = if forward == paging.forward { paging.streak + 1 } else { 0 };
+                paging.forward = forward;
+                paging.start = page.start;
+            }
+            (fresh || turned, paging.forward, paging.streak)
+        };
+        if turned {
+            // count each page once, however often it is redrawn
+            let hits = page_uris.iter().filter(|u| self.tensors.contains_key(u)).count();
+            self.stats.hits += hits;
+            self.stats.misses += page_uris.len() - hits;
+        }
+        self.pin(page_uris.into_iter().chain(attached.cloned()));
+
+        let ahead = page_size * std::cmp::min(1 + streak, MAX_LOOKAHEAD);
+        let after = |n: usize| page.end..page.end+n;
+        let before = |n: usize| page.start.saturating_sub(n)..page.start;
+        self.fetch_missing(uri, page.clone(), item);
+        if forward {
+            self.fetch_missing(uri, after(ahead), item);
+            self.fetch_missing(uri, before(page_size), item);
         }
+        else {
+            self.fetch_missing(uri, before(ahead), item);
+            self.fetch_missing(uri, after(page_size), item);
+        }
+    }
+
+    /// Fetches the items `indices` of the dataset of `uri` that are neither
+    /// cached nor on their way, merging neighbours into one request.
+    fn fetch_missing(&mut self, uri: &H5URI, indices: Range<usize>, item: ItemShape) {
+        let indices = self.clamp(uri, indices);
+        let mut uri_one = uri.clone();
+        let mut run: Option<Range<usize>> = None;
+        for i in indices.clone() {
+            uri_one.query = uri.query.item(i);
+            let missing = !self.tensors.contains_key(&uri_one)
+                && !self.pending.contains(&uri_one)
+                && !self.failed.contains_key(&uri_one);
+            run = match run {
+                Some(r) if missing => Some(r.start..i+1),
+                None if missing => Some(i..i+1),
+                Some(r) => {
+                    self.prefetch(&H5URI { query: uri.query.span(r.start, r.end), ..uri.clone() }, item);
+                    None
+                },
+                None => None
+            };
+        }
+        if let Some(r) = run {
+            self.prefetch(&H5URI { query: uri.query.span(r.start, r.end), ..uri.clone() }, item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `size` bytes of `bits` in `order`.
+    fn encode(bits: u64, size: usize, order: ByteOrder) -> Vec<u8> {
+        let little: Vec<u8> = (0..size).map(|k| (bits >> (8 * k)) as u8).collect();
+        match order {
+            ByteOrder::Little => little,
+            ByteOrder::Big => little.into_iter().rev().collect()
+        }
+    }
+
+    /// One element of each dtype: its bits and its value.
+    fn samples() -> Vec<(Dtype, u64, f32)> {
+        vec![
+            (Dtype::I1, 0xfb, -5.0),
+            (Dtype::U1, 200, 200.0),
+            (Dtype::I2, -300i16 as u16 as u64, -300.0),
+            (Dtype::U2, 60000, 60000.0),
+            (Dtype::I4, -70000i32 as u32 as u64, -70000.0),
+            (Dtype::F2, 0x3e00, 1.5),
+            (Dtype::F4, 3.25f32.to_bits() as u64, 3.25),
+            (Dtype::F8, (-2.5f64).to_bits(), -2.5)
+        ]
+    }
+
+    fn item(channels: usize) -> ItemShape {
+        ItemShape { height: 2, width: 3, channels: channels, channels_first: false }
+    }
+
+    /// `n` elements holding `bits`.
+    fn payload(bits: u64, dtype: &Dtype, order: ByteOrder, n: usize) -> Vec<u8> {
+        (0..n).flat_map(|_| encode(bits, dtype.size(), order)).collect()
+    }
+
+    #[test]
+    fn decodes_every_dtype_in_both_byte_orders() {
+        for &order in &[ByteOrder::Little, ByteOrder::Big] {
+            for (dtype, bits, value) in samples() {
+                let bytes = encode(bits, dtype.size(), order);
+                assert_eq!(decode(&bytes, &dtype, order), vec![value], "{} {:?}", dtype.to_string(), order);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_whole_items() {
+        for &order in &[ByteOrder::Little, ByteOrder::Big] {
+            for (dtype, bits, value) in samples() {
+                let buffer = payload(bits, &dtype, order, 2 * 6);
+                assert_eq!(decode_payload(&buffer, &dtype, &item(1), order), Ok(vec![value; 12]));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        for (dtype, bits, _) in samples() {
+            let buffer = payload(bits, &dtype, ByteOrder::Little, 6);
+            let truncated = &buffer[..buffer.len() - 1];
+            assert_eq!(decode_payload(truncated, &dtype, &item(1), ByteOrder::Little),
+                Err(FetchError::SizeMismatch { expected: 6 * dtype.size(), actual: 6 * dtype.size() - 1 }));
+        }
+    }
+
+    #[test]
+    fn accepts_empty_payloads() {
+        // no items at all, which callers report as a short read
+        for (dtype, _, _) in samples() {
+            assert_eq!(decode_payload(&[], &dtype, &item(1), ByteOrder::Little), Ok(Vec::new()));
+        }
+    }
+
+    #[test]
+    fn rejects_odd_length_payloads() {
+        for (dtype, bits, _) in samples().into_iter().filter(|s| s.0.size() > 1) {
+            let mut buffer = payload(bits, &dtype, ByteOrder::Big, 2 * 6);
+            buffer.push(0);
+            assert_eq!(decode_payload(&buffer, &dtype, &item(1), ByteOrder::Big),
+                Err(FetchError::SizeMismatch { expected: 6 * dtype.size(), actual: 12 * dtype.size() + 1 }));
+        }
+    }
+
+    #[test]
+    fn rejects_payloads_not_matching_the_item_shape() {
+        // two and a half items of 2x3x3 u2
+        let buffer = payload(7, &Dtype::U2, ByteOrder::Little, 45);
+        assert_eq!(decode_payload(&buffer, &Dtype::U2, &item(3), ByteOrder::Little),
+            Err(FetchError::SizeMismatch { expected: 2 * 3 * 3 * 2, actual: 90 }));
+        let empty = ItemShape { height: 0, width: 3, channels: 1, channels_first: false };
+        assert_eq!(decode_payload(&buffer, &Dtype::U2, &empty, ByteOrder::Little),
+            Err(FetchError::SizeMismatch { expected: 0, actual: 90 }));
+    }
+
+    #[test]
+    fn decodes_misaligned_payloads() {
+        for (dtype, bits, value) in samples() {
+            let mut buffer = vec![0u8];
+            buffer.extend(payload(bits, &dtype, ByteOrder::Little, 6));
+            assert_eq!(decode_payload(&buffer[1..], &dtype, &item(1), ByteOrder::Little), Ok(vec![value; 6]));
+        }
+    }
+
+    #[test]
+    fn widens_half_floats() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333251953125);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert!(f16_to_f32(0x8000).is_sign_negative());
+        assert_eq!(f16_to_f32(0x7c00), std::f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), std::f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn reports_short_batches() {
+        let uri = H5URI {
+            path: String::from("file.h5"),
+            h5path: String::from("/images"),
+            query: Query::Range(0, 3),
+            dtype: Dtype::F4
+        };
+        // a batch of three items of which two arrived
+        let data = vec![0.0; 2 * 6];
+        assert!(H5Cache::deserialize(&uri, &data, &item(1), 1).is_ok());
+        assert_eq!(H5Cache::deserialize(&uri, &data, &item(1), 2).err(),
+            Some(FetchError::ShortRead { expected: 3 * 6 * 4, actual: 2 * 6 * 4 }));
+    }
+
+    /// Replies with two items of `item(1)` as f4 to any query.
+    struct ShortSource;
+
+    impl SliceSource for ShortSource {
+        fn connect(&self) -> Result<String, FetchError> {
+            Ok(String::new())
+        }
+
+        fn check(&self, _uri: &H5URI) -> Result<(), FetchError> {
+            Ok(())
+        }
+
+        fn fetch(&self, _uri: &H5URI) -> Result<Vec<u8>, FetchError> {
+            Ok(payload(1.0f32.to_bits() as u64, &Dtype::F4, ByteOrder::Little, 2 * 6))
+        }
+
+        fn byte_order(&self) -> ByteOrder {
+            ByteOrder::Little
+        }
+    }
+
+    #[test]
+    fn marks_items_missing_from_short_replies() {
+        let mut cache = H5Cache::new(Box::new(ShortSource), 1 << 20, None);
+        let uri = H5URI { path: String::new(), h5path: String::from("/x"), query: Query::Range(0, 3), dtype: Dtype::F4 };
+        assert!(cache.request_tensors(&uri, item(1)).iter().all(|r| match r { Ok(None) => true, _ => false }));
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        let mut finished = Vec::new();
+        while finished.len() < 3 {
+            assert!(std::time::Instant::now() < deadline, "fetch did not finish");
+            finished.extend(cache.poll().items);
+            thread::sleep(Duration::from_millis(1));
+        }
+        let results: Vec<Result<bool, FetchError>> = cache.request_tensors(&uri, item(1)).into_iter()
+            .map(|r| r.map(|t| t.is_some())).collect();
+        assert_eq!(results, vec![Ok(true), Ok(true), Err(FetchError::ShortRead { expected: 3 * 6 * 4, actual: 2 * 6 * 4 })]);
+    }
+
+    #[test]
+    fn moves_channels_last() {
+        let uri = H5URI { path: String::new(), h5path: String::new(), query: Query::One(0), dtype: Dtype::F4 };
+        let chw = ItemShape { height: 1, width: 2, channels: 3, channels_first: true };
+        let tensor = H5Cache::deserialize(&uri, &[0.0, 1.0, 10.0, 11.0, 20.0, 21.0], &chw, 0).ok().unwrap();
+        assert_eq!(tensor.shape, vec![1, 2, 3]);
+        assert_eq!(tensor.data, vec![0.0, 10.0, 20.0, 1.0, 11.0, 21.0]);
     }
 }
@@ -9,31 +9,48 @@ extern crate gfx;
 extern crate regex;
 extern crate flate2;
 extern crate uuid;
+extern crate clap;
+extern crate filetime;
+#[cfg(feature = "native")]
+extern crate hdf5;
+#[cfg(feature = "native")]
+extern crate ndarray;
 
+mod cli;
 mod vgui;
 mod h5meta;
 mod h5slice;
+mod render;
+mod plot;
+mod h5disk;
+#[cfg(feature = "native")]
+mod h5native;
 use std::rc::Rc;
-use std::path::PathBuf;
-use vgui::{SpritePrototype, MenuAdapter, VGUIFont};
-use h5meta::{H5Obj, H5Group, H5DatasetFormat};
-use h5slice::{H5URI, Dtype, H5Cache, Query};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use cli::{Options, Backend};
+use vgui::{SpritePrototype, MenuAdapter, Paginatable, VGUIFont, TexImage};
+use h5meta::{H5Obj, H5Group, H5DatasetFormat, Layout};
+use h5slice::{H5URI, ItemShape, Dtype, Tensor, H5Cache, Query, FetchError, SliceSource, SliceServer};
+use render::{Normalization, ChannelMap, Colormap};
+use h5disk::DiskCache;
 use piston_window::*;
 use sprite::*;
 
-const SCREEN_WIDTH: f64 = 1920.0;
-const SCREEN_HEIGHT: f64 = 1080.0;
 const WIDGET_MARGIN: f64 = 15.0;
 const WIDGET_SPACING: f64 = 6.0;
 const ENTRY_HEIGHT: f64 = vgui::ENTRY_HEIGHT as f64;
 const COLUMN_WIDTH: f64 = vgui::COLUMN_WIDTH as f64;
 const LEFT_PANEL_HEND: f64 = WIDGET_MARGIN + COLUMN_WIDTH + WIDGET_SPACING;
 const STATUS_VEND: f64 = WIDGET_MARGIN + ENTRY_HEIGHT + WIDGET_SPACING;
+/// Strip under the grid kept for the colorbar.
+const LEGEND_VSPAN: f64 = WIDGET_SPACING + vgui::COLORBAR_HEIGHT as f64 + WIDGET_MARGIN;
 macro_rules! auto_size {
-    [ left: $a:expr, right: $b:expr ] => { (SCREEN_WIDTH-($a + $b)) };
-    [ left: $a:expr ] => { auto_size![left: $a, right: WIDGET_MARGIN] };
-    [ top: $a:expr, bottom: $b:expr ] => { (SCREEN_HEIGHT-($a + $b)) };
-    [ top: $a:expr ] => { auto_size![top: $a, bottom: WIDGET_MARGIN] };
+    [ $screen:expr; left: $a:expr, right: $b:expr ] => { ($screen.0-($a + $b)) };
+    [ $screen:expr; left: $a:expr ] => { auto_size![$screen; left: $a, right: WIDGET_MARGIN] };
+    [ $screen:expr; top: $a:expr, bottom: $b:expr ] => { ($screen.1-($a + $b)) };
+    [ $screen:expr; top: $a:expr ] => { auto_size![$screen; top: $a, bottom: WIDGET_MARGIN] };
 }
 
 impl MenuAdapter<H5Group> for vgui::Menu {
@@ -69,73 +86,325 @@ fn register_layout<F, R>(scene: &mut sprite::Scene<piston_window::Texture<R>>, l
 {
     let mut sprite_layout = layout.make_sprite(factory);
     sprite_layout.set_position(LEFT_PANEL_HEND, STATUS_VEND);
+    layout.uuid_tiles.clear();
     layout.uuid_self = Some(scene.add_child(sprite_layout));
 }
 
+/// Labels of the items of the open dataset, from a 1-D dataset as long.
+struct Labels {
+    uri: H5URI,
+    item: ItemShape,
+    /// Class names by label, if known.
+    names: Vec<String>
+}
+
+impl Labels {
+    fn new(uri: H5URI, len: usize, names: Vec<String>) -> Labels {
+        Labels { uri: uri, item: ItemShape { height: 1, width: len, channels: 1, channels_first: false }, names: names }
+    }
+
+    /// Captions of items `indices`, empty until the labels are fetched.
+    fn captions(&self, image_cache: &mut H5Cache, indices: Range<usize>) -> Vec<String> {
+        let labels = match image_cache.request_tensors(&self.uri, self.item).pop() {
+            Some(Ok(Some(tensor))) => Some(tensor),
+            _ => None
+        };
+        indices.map(|i| match labels.and_then(|t| t.data.get(i)) {
+            Some(&label) if label >= 0.0 && label == label.trunc() && (label as usize) < self.names.len() =>
+                self.names[label as usize].clone(),
+            Some(&label) => plot::value_label(label),
+            None => String::new()
+        }).collect()
+    }
+}
+
 fn update_page<F, R>(
     pagnator: &vgui::Pagnator,
     mut uri: H5URI,
+    item: ItemShape,
+    labels: Option<&Labels>,
+    font: &VGUIFont,
     image_cache: &mut H5Cache,
-    layout: &vgui::FlowLayout,
+    layout: &mut vgui::FlowLayout,
     scene: &mut sprite::Scene<piston_window::Texture<R>>,
-    factory: &mut F)
+    factory: &mut F) -> Result<(), String>
     where F: gfx::Factory<R>, R: gfx::Resources
 {
+    let mut first_error: Option<FetchError> = None;
+    let mut missing = 0;
     if let Some(page_range) = pagnator.get_range() {
+        image_cache.view(&uri, page_range.clone(), pagnator.page_size, item, labels.map(|l| &l.uri));
+        let captions = labels.map(|l| l.captions(image_cache, page_range.clone())).unwrap_or(Vec::new());
         let sprite_layout = scene.child_mut(layout.uuid_self.unwrap()).unwrap();
-        for (i,p) in page_range.enumerate() {
-            uri.query = Query::One(p);
-            if let Some(im) = image_cache.request(&uri, layout.item_size) {
-                let mut sprite_tex = vgui::sprite_from_image(&im, factory);
-                let position = layout.get_coordinate(i);
-                sprite_tex.set_anchor(0.0, 0.0);
-                sprite_tex.set_position(position.0, position.1);
-                sprite_layout.add_child(sprite_tex);
+        uri.query = uri.query.span(page_range.start, page_range.end);
+        layout.uuid_tiles.clear();
+        for (i, result) in image_cache.request(&uri, item).into_iter().enumerate() {
+            let position = layout.get_coordinate(i);
+            if let Some(caption) = captions.get(i) {
+                let tile = layout.tile_size();
+                let mut sprite_caption = vgui::sprite_from_image(&vgui::caption(caption, font, tile.0), factory);
+                sprite_caption.set_anchor(0.0, 0.0);
+                sprite_caption.set_position(position.0, position.1 + tile.1 as f64);
+                sprite_layout.add_child(sprite_caption);
             }
-            else {
-                // status!("Not available!");
+            let tile = match result {
+                Ok(im) => Some(sprite_layout.add_child(tile_sprite(im, layout, i, factory))),
+                Err(e) => {
+                    // report the first missing tile, keep filling the rest
+                    first_error = first_error.or(Some(e));
+                    missing += 1;
+                    None
+                }
+            };
+            layout.uuid_tiles.push(tile);
+        }
+    }
+    match first_error {
+        None => Ok(()),
+        Some(e) if missing == 1 => Err(e.to_string()),
+        Some(e) => Err(format!("{} ({} items missing)", e, missing))
+    }
+}
+
+/// Sprite of the item at position `i` of the page, `im` or a placeholder
+/// while it is fetched.
+fn tile_sprite<F, R>(im: Option<&TexImage>, layout: &vgui::FlowLayout, i: usize, factory: &mut F) -> Sprite<piston_window::Texture<R>>
+    where F: gfx::Factory<R>, R: gfx::Resources
+{
+    let mut sprite_tex = match im {
+        Some(im) => vgui::sprite_from_image(im, factory),
+        // still being fetched, filled in once it arrives
+        None => vgui::sprite_from_image(&vgui::placeholder(layout.item_size), factory)
+    };
+    let position = layout.get_coordinate(i);
+    sprite_tex.set_anchor(0.0, 0.0);
+    sprite_tex.set_scale(layout.scale, layout.scale);
+    sprite_tex.set_position(position.0, position.1);
+    sprite_tex
+}
+
+/// Redraws just the tiles of the page drawn by `update_page` whose items
+/// are among `finished`.
+fn refresh_page<F, R>(
+    pagnator: &vgui::Pagnator,
+    mut uri: H5URI,
+    item: ItemShape,
+    finished: &[H5URI],
+    image_cache: &mut H5Cache,
+    layout: &mut vgui::FlowLayout,
+    scene: &mut sprite::Scene<piston_window::Texture<R>>,
+    factory: &mut F) -> Result<(), String>
+    where F: gfx::Factory<R>, R: gfx::Resources
+{
+    let mut first_error: Option<FetchError> = None;
+    if let Some(page_range) = pagnator.get_range() {
+        let sprite_layout = scene.child_mut(layout.uuid_self.unwrap()).unwrap();
+        let query = uri.query.clone();
+        for (i, index) in page_range.enumerate() {
+            uri.query = query.item(index);
+            if i >= layout.uuid_tiles.len() || !finished.contains(&uri) {
+                continue;
+            }
+            if let Some(id) = layout.uuid_tiles[i].take() {
+                sprite_layout.remove_child(id);
+            }
+            uri.query = query.span(index, index+1);
+            match image_cache.request(&uri, item).pop() {
+                Some(Ok(im)) => {
+                    let sprite_tex = tile_sprite(im, layout, i, factory);
+                    layout.uuid_tiles[i] = Some(sprite_layout.add_child(sprite_tex));
+                },
+                Some(Err(e)) => first_error = first_error.or(Some(e)),
+                None => ()
             }
         }
     }
+    match first_error {
+        None => Ok(()),
+        Some(e) => Err(e.to_string())
+    }
+}
+
+/// Like `update_page`, for the single item `index` shown in `viewer`, as
+/// drawn by `plot` if given.
+fn update_view<F, R>(
+    mut uri: H5URI,
+    index: usize,
+    item: ItemShape,
+    plot: Option<&dyn Fn(&Tensor) -> TexImage>,
+    image_cache: &mut H5Cache,
+    viewer: &mut vgui::ImageView,
+    scene: &mut sprite::Scene<piston_window::Texture<R>>,
+    factory: &mut F) -> Result<(), String>
+    where F: gfx::Factory<R>, R: gfx::Resources
+{
+    image_cache.view(&uri, index..index+1, 1, item, None);
+    uri.query = uri.query.span(index, index+1);
+    let result = match plot {
+        // plots need the numbers only
+        Some(plot) => match image_cache.request_tensors(&uri, item).pop() {
+            Some(Ok(Some(tensor))) => {
+                viewer.set_image(plot(tensor));
+                Ok(())
+            },
+            Some(Err(e)) => Err(e.to_string()),
+            // still being fetched
+            _ => Ok(())
+        },
+        None => match image_cache.request(&uri, item).pop() {
+            Some(Ok(Some(im))) => {
+                viewer.set_image(im.clone());
+                Ok(())
+            },
+            Some(Err(e)) => Err(e.to_string()),
+            _ => Ok(())
+        }
+    };
+    viewer.update(scene.child_mut(viewer.uuid_self.unwrap()).unwrap(), factory);
+    result
+}
+
+/// Draws the numbers of `tensor` as `kind` says, for a view of `size`.
+fn draw_plot(kind: Layout, tensor: &Tensor, font: &VGUIFont, size: (u32, u32)) -> TexImage {
+    match kind {
+        Layout::Value => plot::value(&plot::value_label(tensor.data[0]), font, size),
+        Layout::Line => plot::line(&tensor.data, font, size),
+        Layout::Histogram => plot::histogram(&tensor.data, font, size),
+        _ => plot::table(&tensor.data, (tensor.shape[0], tensor.shape[1]), font)
+    }
+}
+
+fn load_tree(options: &Options) -> Result<H5Obj, String> {
+    match &options.metadata {
+        Some(fname) => H5Group::parse(fname)
+            .map_err(|e| format!("Cannot read metadata listing {}: {}", fname.display(), e)),
+        #[cfg(feature = "native")]
+        None => H5Group::read(&options.h5file)
+            .map_err(|e| format!("Cannot read HDF5 file {}: {}", options.h5file.display(), e)),
+        #[cfg(not(feature = "native"))]
+        None => unreachable!() // the listing is mandatory without the native feature
+    }
 }
 
 fn main() {
-    let (width, height) = (800, 600);
+    let options = Options::from_args();
+    let h5root = match load_tree(&options) {
+        Ok(root) => root,
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            std::process::exit(1);
+        }
+    };
+    let mut h5pointer = PathBuf::from(&h5root.name());
+    let mut h5open = None;
+    if let Some(h5path) = &options.open {
+        match h5root.find(h5path) {
+            Some(H5Obj::Group(_)) => h5pointer = PathBuf::from(h5path),
+            Some(H5Obj::Dataset(d)) => {
+                h5pointer = PathBuf::from(h5path);
+                h5pointer.pop();
+                h5open = Some(d.name.clone());
+            },
+            None => {
+                eprintln!("error: No group or dataset named {} in {}", h5path, options.h5file.display());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let screen = (options.geometry.0 as f64, options.geometry.1 as f64);
     let opengl = OpenGL::V3_2;
     let mut window: PistonWindow =
-        WindowSettings::new("piston: sprite", (width, height))
+        WindowSettings::new("piston: sprite", options.geometry)
         .exit_on_esc(true)
-        .fullscreen(true)
+        .fullscreen(options.fullscreen)
         .opengl(opengl)
         .build()
         .unwrap();
     let mut scene = Scene::new();
     let font = vgui::load_font("FiraSans-Regular.ttf").expect("Cannot load font.");
-    let h5root = H5Group::parse("/home/alex/datasets/ucm-sample.h5.txt").expect("IO Error");
-    let mut h5pointer = PathBuf::from(&h5root.name());
     let mut menu = vgui::Menu::adapt(h5root.locate_group(&h5pointer).unwrap(), Rc::clone(&font));
     register_menu(&mut scene, &mut menu, &mut window.factory);
 
-    let mut image_cache = H5Cache::new();
+    let source: Box<dyn SliceSource> = match options.backend {
+        Backend::Server => Box::new(SliceServer::new(&options.server)),
+        #[cfg(feature = "native")]
+        Backend::Native => Box::new(h5native::H5File::new(&options.h5file)),
+        #[cfg(not(feature = "native"))]
+        Backend::Native => unreachable!() // rejected by the command-line parser
+    };
+    let disk_cache = match &options.disk_cache {
+        Some(dir) => match DiskCache::open(dir, options.disk_cache_size) {
+            Ok(disk_cache) => Some(disk_cache),
+            Err(e) => {
+                eprintln!("error: Cannot open disk cache {}: {}", dir.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None
+    };
+    let mut image_cache = H5Cache::new(source, options.cache_size, disk_cache);
+    let class_names = options.class_names.as_ref().map(|fname| match std::fs::read_to_string(fname) {
+        Ok(text) => text.lines().map(|line| String::from(line.trim())).collect::<Vec<String>>(),
+        Err(e) => {
+            eprintln!("error: Cannot read class names {}: {}", fname.display(), e);
+            std::process::exit(1);
+        }
+    });
+    let mut normalizations = Normalization::presets();
+    if let Some(normalization) = &options.normalization {
+        normalizations.insert(0, normalization.clone());
+    }
+    let mut norm_current = 0;
+    image_cache.set_normalization(normalizations[norm_current].clone());
+    let colormaps = Colormap::all();
+    let mut cmap_current = colormaps.iter().position(|c| *c == options.colormap).unwrap();
+    image_cache.set_colormap(options.colormap);
     let mut uri = H5URI {
-        path: String::from("/home/alex/datasets/ucm-sample.h5"),
+        path: String::from(options.h5file.to_str().expect("HDF5 file path must be valid UTF-8.")),
         h5path: String::from(""),
         query: Query::One(0),
         dtype: Dtype::F4
     };
     let mut layout = vgui::FlowLayout::view_size((
-            auto_size![ left: LEFT_PANEL_HEND ],
-            auto_size![ top: STATUS_VEND ]
+            auto_size![ screen; left: LEFT_PANEL_HEND ],
+            auto_size![ screen; top: STATUS_VEND, bottom: LEGEND_VSPAN ]
         ));
     register_layout(&mut scene, &mut layout, &mut window.factory);
+    let mut viewer = vgui::ImageView::new(layout.view_size);
+    let mut sprite_viewer = viewer.make_sprite(&mut window.factory);
+    sprite_viewer.set_position(LEFT_PANEL_HEND, STATUS_VEND);
+    sprite_viewer.set_visible(false);
+    viewer.uuid_self = Some(scene.add_child(sprite_viewer));
+    // whether the open dataset is one image, shown in the viewer instead of the grid,
+    // and how if it is plotted
+    let mut single = false;
+    let mut plotted: Option<Layout> = None;
+    // captions of the tiles, or why there are none despite a labels dataset
+    let mut labels: Option<Labels> = None;
+    let mut labels_error: Option<String> = None;
+    // item of the page the grid cursor is on, and the one opened from the grid if any
+    let mut selected = 0;
+    let mut detail: Option<usize> = None;
+    let mut id_highlight = None;
+    // mouse position relative to the grid and viewer, whether it drags the image,
+    // and the values of the pixel under it
+    let mut cursor = (0.0, 0.0);
+    let mut dragging = false;
+    let mut probe = String::new();
+    let mut page_error: Option<String> = None;
     let mut pagnator = None;
+    let mut item_shape = None;
+    // layouts chosen at runtime per dataset, and those the open one can be read as
+    let mut layouts: HashMap<String, Layout> = HashMap::new();
+    let mut layout_candidates = Vec::new();
 
     // Status
     let mut status_bar = vgui::StatusBar {
         label: String::from("Initializing..."),
         font: font.clone(),
         color: image::Rgba([0u8, 0u8, 255u8, 255u8]),
-        width: auto_size![ left: LEFT_PANEL_HEND ] as u32
+        width: auto_size![ screen; left: LEFT_PANEL_HEND ] as u32
     };
     let mut sprite_status = status_bar.make_sprite(&mut window.factory);
     sprite_status.set_position(LEFT_PANEL_HEND, WIDGET_MARGIN);
@@ -146,15 +415,374 @@ fn main() {
         };
     }
 
-    status!("Ready!");
+    // Legend of the colormap, in a strip under the grid, shown while single channels are
+    let mut colorbar = vgui::Colorbar {
+        gradient: Vec::new(),
+        low: String::new(),
+        high: String::new(),
+        font: font.clone(),
+        width: vgui::COLUMN_WIDTH
+    };
+    let mut sprite_colorbar = colorbar.make_sprite(&mut window.factory);
+    sprite_colorbar.set_position(LEFT_PANEL_HEND, screen.1 - WIDGET_MARGIN - vgui::COLORBAR_HEIGHT as f64);
+    sprite_colorbar.set_visible(false);
+    let id_colorbar = scene.add_child(sprite_colorbar);
+    macro_rules! legend {
+        () => {
+            let gray = match image_cache.channel_map() { ChannelMap::Gray(_) => true, _ => false };
+            let sprite_colorbar = scene.child_mut(id_colorbar).unwrap();
+            let gray = gray && item_shape.is_some() && plotted.is_none();
+            sprite_colorbar.set_visible(gray);
+            if gray {
+                let (low, high) = match image_cache.legend(&uri) {
+                    Some((low, high)) => (plot::value_label(low), plot::value_label(high)),
+                    // stretched differently in every image
+                    None => (String::from("per image"), String::new())
+                };
+                colorbar.update(image_cache.colormap().gradient(), low, high, sprite_colorbar, &mut window.factory);
+            }
+        };
+    }
+
+    macro_rules! open_dataset {
+        ( $d:expr, $dpath:expr ) => {
+            let dpath: &str = $dpath;
+            // a --select template of matching rank narrows down what is paged through
+            let slab = options.select.as_ref().and_then(|q| q.apply_template(&$d.shape));
+            let (query, shape) = match slab {
+                Some((query, shape)) => (query, shape),
+                None => (Query::One(0), $d.shape.clone())
+            };
+            layout_candidates = Layout::candidates(&shape);
+            if let Query::Slab { .. } = query {
+                // slab items always come along the paged axis
+                layout_candidates.retain(|l| !l.is_single());
+            }
+            let chosen = layouts.get(dpath).cloned()
+                .or(options.layout.filter(|l| layout_candidates.contains(l)))
+                .or(layout_candidates.first().cloned());
+            let dtype = match (&$d.dtype, &$d.type_name) {
+                (Some(dtype), _) => Ok(dtype.clone()),
+                // plain `h5ls -r` listings carry no type
+                (None, None) => Ok(Dtype::F4),
+                (None, Some(name)) => Err(FetchError::UnknownDtype(name.clone()))
+            };
+            if let (Ok(dtype), Some(fmt)) = (&dtype, chosen.and_then(|l| H5DatasetFormat::with_layout(&shape, l))) {
+                let slab = match query {
+                    Query::Slab { .. } => format!(" [{}]", query.to_string()),
+                    _ => String::new()
+                };
+                // labels index the first axis, so slabs paged along another have none
+                let len = fmt.pagination_range.end;
+                let first_axis = match query { Query::Slab { axis, .. } => axis == 0, _ => true };
+                let labels_path = if fmt.layout.is_single() || !first_axis { None } else {
+                    options.labels.clone().or_else(|| {
+                        let path = Path::new(dpath);
+                        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+                        path.parent().and_then(|parent| h5root.locate_group(parent))
+                            .and_then(|group| group.find_labels(name, len))
+                            .map(|child| String::from(path.with_file_name(child).to_str().unwrap()))
+                    })
+                };
+                labels_error = None;
+                labels = match labels_path.as_ref().and_then(|path| h5root.find(path)) {
+                    Some(H5Obj::Dataset(l)) if l.shape == vec![len] && l.dtype.is_none() && l.type_name.is_some() => {
+                        labels_error = Some(format!("labels {}: {}", labels_path.as_ref().unwrap(),
+                            FetchError::UnknownDtype(l.type_name.clone().unwrap())));
+                        None
+                    },
+                    Some(H5Obj::Dataset(l)) if l.shape == vec![len] => {
+                        let names = class_names.clone().or(l.class_names()).or($d.class_names()).unwrap_or(Vec::new());
+                        let mut labels_uri = uri.clone();
+                        labels_uri.h5path = labels_path.clone().unwrap();
+                        labels_uri.query = Query::All;
+                        labels_uri.dtype = l.dtype.clone().unwrap_or(Dtype::F4);
+                        image_cache.set_length(&labels_uri, 1);
+                        Some(Labels::new(labels_uri, len, names))
+                    },
+                    _ => None
+                };
+                let labelled = match (&labels, &labels_error) {
+                    (Some(labels), _) => format!(" labels {}", labels.uri.h5path),
+                    (None, Some(e)) => format!(" {}", e),
+                    (None, None) => String::new()
+                };
+                status!(format!("Dataset {}{} ({}) {}x[{}] {} {} {}{}",
+                    dpath, slab, fmt.my_shape_to_string(),
+                    fmt.pagination_range.end, fmt.resolution, fmt.format, fmt.layout, $d.storage_to_string(), labelled));
+                scene.remove_child(layout.uuid_self.unwrap());
+                register_layout(&mut scene, &mut layout, &mut window.factory);
+                layouts.insert(String::from(dpath), fmt.layout);
+                layout.item_size = fmt.resolution.into();
+                layout.caption_height = if labels.is_some() { vgui::CAPTION_HEIGHT } else { 0 };
+                layout.fit_scale();
+                pagnator = Some(vgui::Pagnator::new(&layout, fmt.pagination_range.end));
+                item_shape = Some(fmt.item_shape());
+                image_cache.set_channel_map(ChannelMap::default_for(fmt.channels));
+                uri.h5path = String::from(dpath);
+                single = fmt.layout.is_single();
+                plotted = if fmt.layout.is_plot() { Some(fmt.layout) } else { None };
+                detail = None;
+                selected = 0;
+                probe.clear();
+                uri.query = if single { Query::All } else { query.clone() };
+                uri.dtype = dtype.clone();
+                image_cache.set_length(&uri, fmt.pagination_range.end);
+                if options.normalization.is_none() {
+                    // uint8 is displayable as is, anything else gets stretched
+                    let preferred = if uri.dtype == Dtype::U1 { Normalization::Identity } else { Normalization::ImageMinMax };
+                    norm_current = normalizations.iter().position(|x| *x == preferred).unwrap_or(0);
+                    image_cache.set_normalization(normalizations[norm_current].clone());
+                }
+
+                // the disk cache may have the page even if the source cannot serve it
+                // tables are read 1:1
+                viewer.clear(plotted != Some(Layout::Table));
+                let result = if single {
+                    let (view_size, plot_font) = (viewer.view_size, &font);
+                    let draw = plotted.map(|kind| move |tensor: &Tensor| draw_plot(kind, tensor, plot_font, view_size));
+                    update_view(uri.clone(), 0, fmt.item_shape(), draw.as_ref().map(|f| f as &dyn Fn(&Tensor) -> TexImage),
+                        &mut image_cache, &mut viewer, &mut scene, &mut window.factory)
+                }
+                else if let Some(pagnator) = &pagnator {
+                    update_page(&pagnator, uri.clone(), fmt.item_shape(), labels.as_ref(), &font, &mut image_cache, &mut layout, &mut scene, &mut window.factory)
+                }
+                else { Ok(()) };
+                scene.child_mut(viewer.uuid_self.unwrap()).unwrap().set_visible(single);
+                // kept for the status bar until the page is redrawn
+                page_error = image_cache.check(&uri).err().map(|e| e.to_string()).or(result.err());
+                if let Some(e) = &page_error {
+                    status!(format!("Dataset {}: {}", dpath, e));
+                }
+                highlight!();
+                legend!();
+            }
+            else if let Err(e) = dtype {
+                status!(format!("Dataset {}: {}", dpath, e));
+            }
+            else {
+                status!(format!("Unable to visualize dataset with shape: ({})",
+                    H5DatasetFormat::shape_to_string(&shape)));
+            }
+        };
+    }
+
+    macro_rules! show_status {
+        () => {
+            if let (Some(pagnator), Some(item)) = (&pagnator, item_shape) {
+                let channels = match image_cache.channel_map() {
+                    ChannelMap::Gray(_) if item.channels > 1 =>
+                        format!(" [{} of {}, {}]", image_cache.channel_map(), item.channels, image_cache.colormap()),
+                    ChannelMap::Gray(_) => format!(" [{}]", image_cache.colormap()),
+                    _ => format!(" [{} of {}]", image_cache.channel_map(), item.channels)
+                };
+                let zoom = format!("zoom: {:.0}%{}", viewer.zoom * 100.0, if viewer.fitted { " (fit)" } else { "" });
+                let position = match detail {
+                    Some(index) => {
+                        let mut uri_item = uri.clone();
+                        uri_item.query = uri.query.item(index);
+                        format!("item: {} ({}) {}", index, uri_item.to_string(), zoom)
+                    },
+                    None if single => zoom,
+                    None => format!("page: {}/{} scale: {:.0}%", pagnator.page_current+1, pagnator.total(), layout.scale * 100.0)
+                };
+                let page = format!("Dataset {} {} [{}]{} [{}]{}", &uri.h5path,
+                    position, image_cache.normalization(), channels, image_cache.stats(), probe);
+                let page = match &labels_error {
+                    None => page,
+                    Some(e) => format!("{} {}", page, e)
+                };
+                status!(match &page_error {
+                    None => page,
+                    Some(e) => format!("{} {}", page, e)
+                });
+            }
+        };
+    }
+
+    // frames the grid cursor, kept on the page
+    macro_rules! highlight {
+        () => {
+            if let Some(pagnator) = &pagnator {
+                let sprite_layout = scene.child_mut(layout.uuid_self.unwrap()).unwrap();
+                if let Some(id) = id_highlight.take() {
+                    sprite_layout.remove_child(id);
+                }
+                let count = pagnator.get_range().map(|r| r.len()).unwrap_or(0);
+                if !single && count > 0 {
+                    selected = std::cmp::min(selected, count - 1);
+                    let mut sprite_highlight = vgui::sprite_from_image(
+                        &vgui::highlight(layout.tile_size(), layout.spacing), &mut window.factory);
+                    let position = layout.get_coordinate(selected);
+                    let margin = (layout.spacing / 2) as f64;
+                    sprite_highlight.set_anchor(0.0, 0.0);
+                    sprite_highlight.set_position(position.0 - margin, position.1 - margin);
+                    id_highlight = Some(sprite_layout.add_child(sprite_highlight));
+                }
+            }
+        };
+    }
+
+    macro_rules! turn_page {
+        ( $($op:ident)* ) => {
+            if let (Some(pagnator), Some(item)) = (&mut pagnator, item_shape) {
+                scene.remove_child(layout.uuid_self.unwrap());
+                register_layout(&mut scene, &mut layout, &mut window.factory);
+                id_highlight = None;
+                $( pagnator.$op(); )*
+                let viewed = if single { Some(0) } else { detail };
+                let (view_size, plot_font) = (viewer.view_size, &font);
+                let draw = plotted.map(|kind| move |tensor: &Tensor| draw_plot(kind, tensor, plot_font, view_size));
+                let result = match viewed {
+                    Some(index) => update_view(uri.clone(), index, item, draw.as_ref().map(|f| f as &dyn Fn(&Tensor) -> TexImage),
+                        &mut image_cache, &mut viewer, &mut scene, &mut window.factory),
+                    None => update_page(&pagnator, uri.clone(), item, labels.as_ref(), &font, &mut image_cache, &mut layout, &mut scene, &mut window.factory)
+                };
+                // the grid stays where it was while one of its items is opened
+                scene.child_mut(layout.uuid_self.unwrap()).unwrap().set_visible(viewed.is_none());
+                scene.child_mut(viewer.uuid_self.unwrap()).unwrap().set_visible(viewed.is_some());
+                page_error = image_cache.check(&uri).err().map(|e| e.to_string()).or(result.err());
+            }
+            highlight!();
+            show_status!();
+            legend!();
+        };
+    }
+
+    // redraws what shows the `finished` items, leaving the rest of the page be
+    macro_rules! refresh {
+        ( $finished:expr ) => {
+            let finished: Vec<H5URI> = $finished;
+            let viewed = if single { Some(0) } else { detail };
+            let captioned = labels.as_ref().map(|l| viewed.is_none() && finished.contains(&l.uri)).unwrap_or(false);
+            if captioned {
+                // every caption changes
+                turn_page!();
+            }
+            else if let (Some(pagnator), Some(item)) = (&pagnator, item_shape) {
+                let result = match viewed {
+                    Some(index) => {
+                        let mut uri_item = uri.clone();
+                        uri_item.query = uri.query.item(index);
+                        if finished.contains(&uri_item) {
+                            let (view_size, plot_font) = (viewer.view_size, &font);
+                            let draw = plotted.map(|kind| move |tensor: &Tensor| draw_plot(kind, tensor, plot_font, view_size));
+                            update_view(uri.clone(), index, item, draw.as_ref().map(|f| f as &dyn Fn(&Tensor) -> TexImage),
+                                &mut image_cache, &mut viewer, &mut scene, &mut window.factory)
+                        }
+                        else { Ok(()) }
+                    },
+                    None => refresh_page(&pagnator, uri.clone(), item, &finished, &mut image_cache, &mut layout, &mut scene, &mut window.factory)
+                };
+                if let Err(e) = result {
+                    page_error = Some(e);
+                    show_status!();
+                }
+                // the range of the dataset may have grown
+                legend!();
+            }
+        };
+    }
+
+    // values under the mouse of the item in the viewer
+    macro_rules! probe {
+        () => {
+            probe.clear();
+            // plots show their numbers already
+            let viewed = if plotted.is_some() { None } else if single { Some(0) } else { detail };
+            if let (Some(index), true, Some((x, y))) = (viewed, viewer.contains(cursor), viewer.get_pixel(cursor)) {
+                let mut uri_item = uri.clone();
+                uri_item.query = uri.query.item(index);
+                if let Some(tensor) = image_cache.tensor(&uri_item) {
+                    let channels = tensor.shape[2];
+                    let offset = (y as usize * tensor.shape[1] + x as usize) * channels;
+                    let (values, _) = image_cache.channel_map().pick(&tensor.data[offset..offset+channels], channels);
+                    probe = format!(" ({}, {}): {}", x, y,
+                        values.iter().map(|&v| plot::value_label(v)).collect::<Vec<String>>().join(", "));
+                }
+            }
+        };
+    }
+
+    // answered by `poll`, the window staying responsive meanwhile
+    macro_rules! connect {
+        () => {
+            image_cache.connect();
+            status!("Connecting...");
+        };
+    }
+
+    connect!();
+    if let Some(dataset_name) = h5open {
+        h5pointer.push(&dataset_name);
+        if let H5Obj::Dataset(d) = h5root.locate(&h5pointer) {
+            open_dataset!(d, h5pointer.to_str().unwrap());
+        }
+        h5pointer.pop();
+        let (sid, shift) = menu.seek(&dataset_name);
+        scene.run(sid, &shift);
+    }
     while let Some(e) = window.next() {
         scene.event(&e);
+        let polled = image_cache.poll();
+        match polled.connected {
+            Some(Ok(description)) if item_shape.is_none() => status!(format!("Ready! {}", description)),
+            // fetch again what failed before
+            Some(Ok(_)) => { turn_page!(); },
+            Some(Err(e)) => status!(e.to_string()),
+            None => ()
+        }
+        if !polled.items.is_empty() {
+            refresh!(polled.items);
+        }
 
         window.draw_2d(&e, |c, g| {
             clear([1.0, 1.0, 1.0, 1.0], g);
             scene.draw(c.transform, g);
         });
 
+        let viewing = single || detail.is_some();
+        if let Some(position) = e.mouse_cursor_args() {
+            let at = (position[0] - LEFT_PANEL_HEND, position[1] - STATUS_VEND);
+            if dragging {
+                viewer.pan((at.0 - cursor.0, at.1 - cursor.1));
+                viewer.update(scene.child_mut(viewer.uuid_self.unwrap()).unwrap(), &mut window.factory);
+            }
+            cursor = at;
+            if viewing {
+                let last = probe.clone();
+                probe!();
+                if probe != last {
+                    show_status!();
+                }
+            }
+        }
+        if let Some(scroll) = e.mouse_scroll_args() {
+            if viewing && viewer.contains(cursor) {
+                viewer.zoom_at(1.25f64.powf(scroll[1]), cursor);
+                viewer.update(scene.child_mut(viewer.uuid_self.unwrap()).unwrap(), &mut window.factory);
+                probe!();
+                show_status!();
+            }
+        }
+        match (e.press_args(), e.release_args()) {
+            (Some(Button::Mouse(MouseButton::Left)), _) if viewing => dragging = viewer.contains(cursor),
+            (Some(Button::Mouse(MouseButton::Left)), _) => {
+                // a click on a tile opens it
+                let page_range = pagnator.as_ref().and_then(|p| p.get_range());
+                if let (Some(i), Some(page_range)) = (layout.get_index(cursor), page_range) {
+                    if i < page_range.len() {
+                        selected = i;
+                        detail = Some(page_range.start + i);
+                        viewer.clear(true);
+                        turn_page!();
+                    }
+                }
+            },
+            (_, Some(Button::Mouse(MouseButton::Left))) => dragging = false,
+            _ => {}
+        }
+
         if let Some(Button::Keyboard(key)) = e.press_args() {
             match key {
                 Key::Down => {
@@ -183,24 +811,7 @@ fn main() {
                                         register_menu(&mut scene, &mut menu, &mut window.factory);
                                     },
                                     H5Obj::Dataset(d) => {
-                                        if let Some(resolution) = H5DatasetFormat::resolution_batch_images(&d.shape) {
-                                            let dpath = h5pointer.to_str().unwrap();
-                                            let fmt = H5DatasetFormat::batch(&d.shape);
-                                            status!(format!("Dataset {} ({}) {}x[{}] {}",
-                                                dpath, fmt.my_shape_to_string(),
-                                                fmt.pagination_range.end, resolution, fmt.format));
-                                            layout.item_size = resolution.into();
-                                            pagnator = Some(vgui::Pagnator::new(&layout, fmt.pagination_range.end));
-                                            uri.h5path = String::from(dpath);
-
-                                            if let Some(pagnator) = &pagnator {
-                                                update_page(&pagnator, uri.clone(), &mut image_cache, &layout, &mut scene, &mut window.factory);
-                                            }
-                                        }
-                                        else {
-                                            status!(format!("Unable to visualize dataset with shape: ({})",
-                                                H5DatasetFormat::shape_to_string(&d.shape)));
-                                        }
+                                        open_dataset!(d, h5pointer.to_str().unwrap());
                                         h5pointer.pop();
                                     }
                                 }
@@ -216,26 +827,110 @@ fn main() {
                         register_menu(&mut scene, &mut menu, &mut window.factory);
                     }
                 },
-                Key::Comma => {
-                    if let Some(pagnator) = &mut pagnator {
-                        scene.remove_child(layout.uuid_self.unwrap());
-                        register_layout(&mut scene, &mut layout, &mut window.factory);
-                        pagnator.dec();
-                        update_page(&pagnator, uri.clone(), &mut image_cache, &layout, &mut scene, &mut window.factory);
+                Key::R => {
+                    connect!();
+                },
+                Key::L => {
+                    // read the open dataset under its next possible layout
+                    let dpath = uri.h5path.clone();
+                    if let (Some(current), Some(H5Obj::Dataset(d))) = (layouts.get(&dpath).cloned(), h5root.find(&dpath)) {
+                        if let Some(i) = layout_candidates.iter().position(|l| *l == current) {
+                            let next = layout_candidates[(i + 1) % layout_candidates.len()];
+                            layouts.insert(dpath.clone(), next);
+                            open_dataset!(d, &dpath);
+                        }
+                    }
+                },
+                Key::N => {
+                    norm_current = (norm_current + 1) % normalizations.len();
+                    image_cache.set_normalization(normalizations[norm_current].clone());
+                    turn_page!();
+                },
+                Key::K => {
+                    cmap_current = (cmap_current + 1) % colormaps.len();
+                    image_cache.set_colormap(colormaps[cmap_current]);
+                    turn_page!();
+                },
+                Key::C | Key::V | Key::M => {
+                    // next/previous channel, or switch between one channel and three
+                    if let Some(item) = item_shape {
+                        let channel_map = match key {
+                            Key::C => image_cache.channel_map().shift(1, item.channels),
+                            Key::V => image_cache.channel_map().shift(-1, item.channels),
+                            _ => image_cache.channel_map().toggle(item.channels)
+                        };
+                        image_cache.set_channel_map(channel_map);
+                        turn_page!();
                     }
-                    if let Some(pagnator) = &pagnator {
-                        status!(format!("Dataset {} page: {}/{}", &uri.h5path, pagnator.page_current+1, pagnator.total()));
+                },
+                Key::F | Key::D1 => {
+                    // fit to the window, or one screen pixel per image pixel
+                    if viewing {
+                        if key == Key::F { viewer.fit(); } else { viewer.actual_size(); }
+                        viewer.update(scene.child_mut(viewer.uuid_self.unwrap()).unwrap(), &mut window.factory);
+                        probe!();
+                        show_status!();
                     }
                 },
-                Key::Period => {
-                    if let Some(pagnator) = &mut pagnator {
-                        scene.remove_child(layout.uuid_self.unwrap());
-                        register_layout(&mut scene, &mut layout, &mut window.factory);
-                        pagnator.inc();
-                        update_page(&pagnator, uri.clone(), &mut image_cache, &layout, &mut scene, &mut window.factory);
+                Key::Equals | Key::Minus => {
+                    let factor = if key == Key::Equals { 1.25 } else { 0.8 };
+                    if viewing {
+                        let middle = (viewer.view_size.0 as f64 / 2.0, viewer.view_size.1 as f64 / 2.0);
+                        viewer.zoom_at(factor, middle);
+                        viewer.update(scene.child_mut(viewer.uuid_self.unwrap()).unwrap(), &mut window.factory);
+                        probe!();
+                        show_status!();
+                    }
+                    else if pagnator.is_some() {
+                        // resize the tiles, keeping the grid cursor on its item
+                        if let Some(pagnator) = &mut pagnator {
+                            let current = pagnator.page_current * pagnator.page_size + selected;
+                            layout.zoom(factor);
+                            selected = pagnator.set_page_size(layout.page_capacity(), current);
+                        }
+                        turn_page!();
+                    }
+                },
+                Key::W | Key::A | Key::S | Key::D => {
+                    // move the grid cursor
+                    if !viewing {
+                        let row = layout.get_items_per_row();
+                        selected = match key {
+                            Key::A => selected.saturating_sub(1),
+                            Key::D => selected + 1,
+                            Key::W if selected >= row => selected - row,
+                            Key::S => selected + row,
+                            _ => selected
+                        };
+                        highlight!();
+                    }
+                },
+                Key::Return => {
+                    if !viewing {
+                        if let Some(page_range) = pagnator.as_ref().and_then(|p| p.get_range()) {
+                            detail = Some(page_range.start + selected);
+                            viewer.clear(true);
+                            turn_page!();
+                        }
                     }
-                    if let Some(pagnator) = &pagnator {
-                        status!(format!("Dataset {} page: {}/{}", &uri.h5path, pagnator.page_current+1, pagnator.total()));
+                },
+                Key::Backspace => {
+                    // back to the grid, at the page it was left
+                    if detail.is_some() {
+                        detail = None;
+                        dragging = false;
+                        probe.clear();
+                        turn_page!();
+                    }
+                },
+                Key::Comma => {
+                    if !viewing {
+                        turn_page!(dec);
+                    }
+                },
+                Key::Period => {
+                    if !viewing {
+                        turn_page!(inc);
                     }
                 }
                 _ => {}
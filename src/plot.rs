@@ -0,0 +1,180 @@
+use std;
+use std::borrow::Borrow;
+use image::{Rgba, RgbaImage};
+use imageproc;
+use imageproc::rect::Rect;
+use rusttype;
+use render::min_max;
+use vgui::{VGUIFont, TexImage, text_width};
+
+const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+const INK: [u8; 4] = [0, 0, 0, 255];
+const DATA: [u8; 4] = [0, 0, 255, 255];
+const HEADER: [u8; 4] = [230, 230, 240, 255];
+const LABEL_SIZE: f32 = 24.0;
+/// Space around the plot area, the left and bottom sides holding the axis
+/// labels.
+const MARGIN: u32 = 16;
+const MARGIN_LEFT: u32 = 120;
+const MARGIN_BOTTOM: u32 = 40;
+/// Size of a table cell, and the most rows and columns drawn.
+const CELL: (u32, u32) = (120, 28);
+const MAX_ROWS: usize = 128;
+const MAX_COLUMNS: usize = 32;
+/// Most bins of a histogram of non-integer values.
+const MAX_BINS: usize = 64;
+
+/// Short text of a value.
+pub fn value_label(x: f32) -> String {
+    if x == x.trunc() && x.abs() < 1e6 { format!("{}", x) }
+    else if x.abs() >= 1e-2 && x.abs() < 1e4 { format!("{:.3}", x) }
+    else { format!("{:.2e}", x) }
+}
+
+fn canvas(size: (u32, u32)) -> RgbaImage {
+    RgbaImage::from_pixel(size.0, size.1, Rgba(BACKGROUND))
+}
+
+/// Draws `text` with its top left corner at `x`, `y`, or its top right
+/// corner if `right_aligned`.
+fn label(image: &mut RgbaImage, font: &VGUIFont, x: u32, y: u32, text: &str, right_aligned: bool) {
+    let scale = rusttype::Scale { x: LABEL_SIZE, y: LABEL_SIZE };
+    let x = if right_aligned { x.saturating_sub(text_width(font, scale, text)) } else { x };
+    imageproc::drawing::draw_text_mut(image, Rgba(INK), x, y, scale, font.borrow(), text);
+}
+
+/// Part of a `size` image to plot in: left, top, width, height.
+fn plot_area(size: (u32, u32)) -> (u32, u32, u32, u32) {
+    (MARGIN_LEFT, MARGIN,
+     std::cmp::max(1, size.0.saturating_sub(MARGIN_LEFT + MARGIN)),
+     std::cmp::max(1, size.1.saturating_sub(MARGIN + MARGIN_BOTTOM)))
+}
+
+/// Frames `area` of `image`, labelled with the values at its bottom and top
+/// on the left and those at its left and right below.
+fn axes(image: &mut RgbaImage, font: &VGUIFont, area: (u32, u32, u32, u32), y_range: (String, String), x_range: (String, String)) {
+    let (left, top, width, height) = area;
+    imageproc::drawing::draw_hollow_rect_mut(image, Rect::at(left as i32 - 1, top as i32 - 1).of_size(width + 2, height + 2), Rgba(INK));
+    label(image, font, left - 8, top, &y_range.1, true);
+    label(image, font, left - 8, top + height - LABEL_SIZE as u32, &y_range.0, true);
+    label(image, font, left, top + height + 8, &x_range.0, false);
+    label(image, font, left + width, top + height + 8, &x_range.1, true);
+}
+
+/// `(low, high)` widened if empty, so values can be scaled by it.
+fn widen((low, high): (f32, f32)) -> (f32, f32) {
+    if high > low { (low, high) } else { (low - 1.0, high + 1.0) }
+}
+
+/// One value, written large in the middle of a `size` image.
+pub fn value(text: &str, font: &VGUIFont, size: (u32, u32)) -> TexImage {
+    let mut image = canvas(size);
+    let scale = rusttype::Scale { x: 4.0 * LABEL_SIZE, y: 4.0 * LABEL_SIZE };
+    let x = size.0.saturating_sub(text_width(font, scale, text)) / 2;
+    let y = size.1.saturating_sub(scale.y as u32) / 2;
+    imageproc::drawing::draw_text_mut(&mut image, Rgba(INK), x, y, scale, font.borrow(), text);
+    image
+}
+
+/// `values` against their index. With more values than pixel columns, each
+/// column shows the range of the values falling in it.
+pub fn line(values: &[f32], font: &VGUIFont, size: (u32, u32)) -> TexImage {
+    let mut image = canvas(size);
+    let area = plot_area(size);
+    let (left, top, width, height) = area;
+    let (low, high) = min_max(values).unwrap_or((0.0, 1.0));
+    let (y_low, y_high) = widen((low, high));
+    let y = |v: f32| top as f32 + height as f32 * (1.0 - (v - y_low) / (y_high - y_low));
+    let n = values.len();
+    if n <= width as usize {
+        let x = |i: usize| left as f32 + if n > 1 { i as f32 * (width - 1) as f32 / (n - 1) as f32 } else { 0.0 };
+        for i in 0..n {
+            if !values[i].is_finite() {
+                continue;
+            }
+            if i + 1 < n && values[i+1].is_finite() {
+                imageproc::drawing::draw_line_segment_mut(&mut image, (x(i), y(values[i])), (x(i+1), y(values[i+1])), Rgba(DATA));
+            }
+            else {
+                imageproc::drawing::draw_filled_rect_mut(&mut image, Rect::at(x(i) as i32 - 1, y(values[i]) as i32 - 1).of_size(3, 3), Rgba(DATA));
+            }
+        }
+    }
+    else {
+        for column in 0..width as usize {
+            let chunk = &values[column * n / width as usize..(column + 1) * n / width as usize];
+            if let Some((a, b)) = min_max(chunk) {
+                let x = (left as usize + column) as f32;
+                imageproc::drawing::draw_line_segment_mut(&mut image, (x, y(a)), (x, y(b)), Rgba(DATA));
+            }
+        }
+    }
+    axes(&mut image, font, area, (value_label(low), value_label(high)),
+        (String::from("0"), n.saturating_sub(1).to_string()));
+    image
+}
+
+/// Counts of `values` in even bins over their range, one per integer if
+/// they are integers from a small range, like labels.
+pub fn histogram(values: &[f32], font: &VGUIFont, size: (u32, u32)) -> TexImage {
+    let mut image = canvas(size);
+    let area = plot_area(size);
+    let (left, top, width, height) = area;
+    let finite: Vec<f32> = values.iter().cloned().filter(|x| x.is_finite()).collect();
+    let (low, high) = min_max(&finite).unwrap_or((0.0, 1.0));
+    let integers = finite.iter().all(|x| *x == x.trunc()) && high - low < width as f32;
+    let (bins, low, high) = if integers {
+        // bins centered on the integers
+        ((high - low) as usize + 1, low - 0.5, high + 0.5)
+    } else {
+        let (low, high) = widen((low, high));
+        (std::cmp::max(1, std::cmp::min(MAX_BINS, (finite.len() as f64).sqrt() as usize)), low, high)
+    };
+    let mut counts = vec![0usize; bins];
+    for x in &finite {
+        let bin = ((x - low) / (high - low) * bins as f32) as usize;
+        counts[std::cmp::min(bin, bins - 1)] += 1;
+    }
+    let most = std::cmp::max(1, *counts.iter().max().unwrap_or(&0));
+    for (i, &count) in counts.iter().enumerate() {
+        let (x0, x1) = (i as u32 * width / bins as u32, (i as u32 + 1) * width / bins as u32);
+        let bar = (count as u64 * height as u64 / most as u64) as u32;
+        if bar > 0 {
+            // a pixel between bars as long as they are wide enough
+            let gap = if x1 - x0 > 2 { 1 } else { 0 };
+            imageproc::drawing::draw_filled_rect_mut(&mut image,
+                Rect::at((left + x0) as i32, (top + height - bar) as i32).of_size(std::cmp::max(1, x1 - x0 - gap), bar), Rgba(DATA));
+        }
+    }
+    let x_range = if integers { (value_label(low + 0.5), value_label(high - 0.5)) } else { (value_label(low), value_label(high)) };
+    axes(&mut image, font, area, (String::from("0"), most.to_string()), x_range);
+    image
+}
+
+/// Rows by columns of `values` as text, headed by their indices. Beyond
+/// `MAX_ROWS` and `MAX_COLUMNS` they are cut off, the corner cell giving the
+/// whole shape.
+pub fn table(values: &[f32], shape: (usize, usize), font: &VGUIFont) -> TexImage {
+    let (rows, columns) = (std::cmp::min(shape.0, MAX_ROWS), std::cmp::min(shape.1, MAX_COLUMNS));
+    let size = ((columns as u32 + 1) * CELL.0, (rows as u32 + 1) * CELL.1);
+    let mut image = canvas(size);
+    imageproc::drawing::draw_filled_rect_mut(&mut image, Rect::at(0, 0).of_size(size.0, CELL.1), Rgba(HEADER));
+    imageproc::drawing::draw_filled_rect_mut(&mut image, Rect::at(0, 0).of_size(CELL.0, size.1), Rgba(HEADER));
+    let right = |column: usize| (column as u32 + 2) * CELL.0 - 6;
+    let below = |row: usize| (row as u32 + 1) * CELL.1 + 2;
+    if rows < shape.0 || columns < shape.1 {
+        label(&mut image, font, 6, 2, &format!("{}x{}", shape.0, shape.1), false);
+    }
+    for column in 0..columns {
+        label(&mut image, font, right(column), 2, &column.to_string(), true);
+    }
+    for row in 0..rows {
+        label(&mut image, font, CELL.0 - 6, below(row), &row.to_string(), true);
+        for column in 0..columns {
+            if let Some(&x) = values.get(row * shape.1 + column) {
+                label(&mut image, font, right(column), below(row), &value_label(x), true);
+            }
+        }
+    }
+    image
+}
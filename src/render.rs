@@ -0,0 +1,340 @@
+use std;
+use std::fmt;
+use std::str::FromStr;
+use image;
+use h5slice::{Dtype, Tensor, TexImage};
+
+/// How element values are mapped onto 0..255 pixel values.
+#[derive(Clone, PartialEq)]
+pub enum Normalization {
+    /// Values as they are, clamped. Small integer types are scaled by their
+    /// full range, which makes this a no-op for uint8.
+    Identity,
+    /// Stretch the min..max of each image.
+    ImageMinMax,
+    /// Stretch the min..max of everything fetched from the dataset so far.
+    DatasetMinMax,
+    /// Stretch a fixed value window.
+    Window(f32, f32),
+    /// Undo `(x - mean) / std` standardization, with mean and std per channel
+    /// in pixel units, e.g. `123.7,116.3,103.5/58.4,57.1,57.4` for ImageNet.
+    Destandardize(Vec<f32>, Vec<f32>),
+    /// Stretch between two percentiles of each image, clipping outliers.
+    Percentile(f32, f32)
+}
+
+impl Normalization {
+    /// Presets cycled through at runtime.
+    pub fn presets() -> Vec<Normalization> {
+        vec![
+            Normalization::ImageMinMax,
+            Normalization::DatasetMinMax,
+            Normalization::Percentile(2.0, 98.0),
+            Normalization::Identity
+        ]
+    }
+
+    /// Maps channel-interleaved `data` onto pixel values, `sources` giving
+    /// the channel of the tensor each of its channels was picked from.
+    /// `dataset_range` is the min..max seen so far in the dataset the data
+    /// belongs to.
+    pub fn apply(&self, data: &[f32], sources: &[usize], dtype: &Dtype, dataset_range: Option<(f32, f32)>) -> Vec<u8> {
+        match self {
+            Normalization::Destandardize(mean, stdev) => data.iter().enumerate().map(|(i, x)| {
+                let c = sources[i % sources.len()];
+                let (m, s) = (mean[c % mean.len()], stdev[c % stdev.len()]);
+                to_u8(x * s + m)
+            }).collect(),
+            _ => {
+                let (low, high) = self.bounds(data, dtype, dataset_range).unwrap_or((0.0, 255.0));
+                stretch(data, low, high)
+            }
+        }
+    }
+
+    /// Values mapped to 0 and 255, if there are such. With empty `data`, the
+    /// ones that hold for any image of the dataset.
+    pub fn bounds(&self, data: &[f32], dtype: &Dtype, dataset_range: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        match self {
+            Normalization::Identity => Some(dtype.value_range().unwrap_or((0.0, 255.0))),
+            Normalization::ImageMinMax => min_max(data),
+            Normalization::DatasetMinMax => dataset_range.or_else(|| min_max(data)),
+            Normalization::Window(low, high) => Some((*low, *high)),
+            Normalization::Percentile(p_low, p_high) => percentiles(data, *p_low, *p_high),
+            Normalization::Destandardize(_, _) => None
+        }
+    }
+}
+
+/// How single channel values are colored.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colormap {
+    Gray,
+    Viridis,
+    Magma,
+    Jet,
+    /// Diverging red to blue, centered on zero.
+    RdBu
+}
+
+impl Colormap {
+    /// All colormaps, cycled through at runtime.
+    pub fn all() -> Vec<Colormap> {
+        vec![Colormap::Gray, Colormap::Viridis, Colormap::Magma, Colormap::Jet, Colormap::RdBu]
+    }
+
+    /// Evenly spaced colors interpolated in between, after matplotlib.
+    fn stops(&self) -> &'static [[u8; 3]] {
+        const GRAY: &[[u8; 3]] = &[[0, 0, 0], [255, 255, 255]];
+        const VIRIDIS: &[[u8; 3]] = &[
+            [68, 1, 84], [71, 45, 123], [59, 82, 139], [44, 114, 142], [33, 145, 140],
+            [40, 174, 128], [94, 201, 98], [173, 220, 48], [253, 231, 37]];
+        const MAGMA: &[[u8; 3]] = &[
+            [0, 0, 4], [24, 15, 61], [68, 15, 118], [114, 31, 129], [158, 47, 127],
+            [205, 64, 113], [241, 96, 93], [253, 150, 104], [254, 202, 141], [252, 253, 191]];
+        const JET: &[[u8; 3]] = &[
+            [0, 0, 127], [0, 0, 255], [0, 127, 255], [0, 255, 255], [127, 255, 127],
+            [255, 255, 0], [255, 127, 0], [255, 0, 0], [127, 0, 0]];
+        const RDBU: &[[u8; 3]] = &[
+            [103, 0, 31], [178, 24, 43], [214, 96, 77], [244, 165, 130], [253, 219, 199], [247, 247, 247],
+            [209, 229, 240], [146, 197, 222], [67, 147, 195], [33, 102, 172], [5, 48, 97]];
+        match self {
+            Colormap::Gray => GRAY,
+            Colormap::Viridis => VIRIDIS,
+            Colormap::Magma => MAGMA,
+            Colormap::Jet => JET,
+            Colormap::RdBu => RDBU
+        }
+    }
+
+    /// Colors of pixel values 0..255.
+    pub fn gradient(&self) -> Vec<[u8; 3]> {
+        let stops = self.stops();
+        let segments = (stops.len() - 1) as f32;
+        (0..256).map(|v| {
+            let x = v as f32 / 255.0 * segments;
+            let k = std::cmp::min(x as usize, stops.len() - 2);
+            let t = x - k as f32;
+            let mut color = [0u8; 3];
+            for c in 0..3 {
+                color[c] = (stops[k][c] as f32 * (1.0 - t) + stops[k+1][c] as f32 * t).round() as u8;
+            }
+            color
+        }).collect()
+    }
+
+    /// Whether the middle color stands for zero.
+    pub fn is_diverging(&self) -> bool {
+        *self == Colormap::RdBu
+    }
+
+    /// `(low, high)` widened to be symmetric around zero for a diverging
+    /// colormap.
+    pub fn center(&self, (low, high): (f32, f32)) -> (f32, f32) {
+        if self.is_diverging() {
+            let m = low.abs().max(high.abs());
+            (-m, m)
+        }
+        else { (low, high) }
+    }
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Colormap, String> {
+        Colormap::all().into_iter().find(|c| c.to_string() == s.to_lowercase())
+            .ok_or(format!("Unknown colormap \"{}\"", s))
+    }
+}
+
+/// Which channels of a tensor make up the display image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChannelMap {
+    /// One channel shown as gray.
+    Gray(usize),
+    /// Three channels shown as red, green and blue.
+    Rgb(usize, usize, usize)
+}
+
+impl ChannelMap {
+    /// The natural view of data with `channels` channels: RGB of the first
+    /// three if there are as many, gray of the first otherwise.
+    pub fn default_for(channels: usize) -> ChannelMap {
+        if channels >= 3 { ChannelMap::Rgb(0, 1, 2) } else { ChannelMap::Gray(0) }
+    }
+
+    /// Every channel moved by `step`, wrapping around.
+    pub fn shift(&self, step: isize, channels: usize) -> ChannelMap {
+        let n = std::cmp::max(channels, 1) as isize;
+        let at = |c: usize| (((c as isize + step) % n + n) % n) as usize;
+        match *self {
+            ChannelMap::Gray(c) => ChannelMap::Gray(at(c)),
+            ChannelMap::Rgb(r, g, b) => ChannelMap::Rgb(at(r), at(g), at(b))
+        }
+    }
+
+    /// Gray of the red channel, or RGB of the gray channel and the next two.
+    pub fn toggle(&self, channels: usize) -> ChannelMap {
+        let n = std::cmp::max(channels, 1);
+        match *self {
+            ChannelMap::Gray(c) => ChannelMap::Rgb(c, (c + 1) % n, (c + 2) % n),
+            ChannelMap::Rgb(r, _, _) => ChannelMap::Gray(r)
+        }
+    }
+
+    /// Channels picked out of `channels`, clamped to those there are.
+    pub fn sources(&self, channels: usize) -> Vec<usize> {
+        let last = channels - 1;
+        match *self {
+            ChannelMap::Gray(c) => vec![std::cmp::min(c, last)],
+            ChannelMap::Rgb(r, g, b) => vec![std::cmp::min(r, last), std::cmp::min(g, last), std::cmp::min(b, last)]
+        }
+    }
+
+    /// Picks the mapped channels out of channels-last `data`, clamping
+    /// channels the data lacks. Returns them with the channel each came from.
+    pub fn pick(&self, data: &[f32], channels: usize) -> (Vec<f32>, Vec<usize>) {
+        let sources = self.sources(channels);
+        let data = if sources.len() == channels && sources.iter().enumerate().all(|(i, &c)| i == c) { data.to_vec() }
+            else { data.chunks(channels).flat_map(|px| sources.iter().map(move |&c| px[c])).collect() };
+        (data, sources)
+    }
+}
+
+impl fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelMap::Gray(c) => write!(f, "channel {}", c),
+            ChannelMap::Rgb(r, g, b) => write!(f, "rgb {},{},{}", r, g, b)
+        }
+    }
+}
+
+/// Derives the display image of a channels-last `tensor` holding `dtype`
+/// values, made of the channels `channel_map` picks. Only those are
+/// normalized; a single one is colored by `colormap`.
+pub fn to_image(tensor: &Tensor, dtype: &Dtype, channel_map: &ChannelMap, colormap: &Colormap,
+    normalization: &Normalization, dataset_range: Option<(f32, f32)>) -> TexImage
+{
+    let (height, width, channels) = (tensor.shape[0], tensor.shape[1], tensor.shape[2]);
+    let (data, sources) = channel_map.pick(&tensor.data, channels);
+    let pixels = if sources.len() == 3 { normalization.apply(&data, &sources, dtype, dataset_range) } else {
+        let values = match normalization.bounds(&data, dtype, dataset_range) {
+            Some(bounds) if colormap.is_diverging() => {
+                let (low, high) = colormap.center(bounds);
+                stretch(&data, low, high)
+            },
+            _ => normalization.apply(&data, &sources, dtype, dataset_range)
+        };
+        let gradient = colormap.gradient();
+        values.iter().flat_map(|&v| gradient[v as usize].to_vec()).collect()
+    };
+    image::DynamicImage::ImageRgb8(image::ImageBuffer::from_raw(
+        width as u32, height as u32, pixels).unwrap()).to_rgba()
+}
+
+fn to_u8(x: f32) -> u8 {
+    if x.is_nan() { 0 }
+    else { x.max(0.0).min(255.0) as u8 }
+}
+
+/// Linearly maps low..high onto 0..255, clamping whatever falls outside.
+fn stretch(data: &[f32], low: f32, high: f32) -> Vec<u8> {
+    let scale = if high > low { 255.0 / (high - low) } else { 0.0 };
+    data.iter().map(|x| to_u8((x - low) * scale)).collect()
+}
+
+/// Range of the finite values in `data`.
+pub fn min_max(data: &[f32]) -> Option<(f32, f32)> {
+    data.iter().filter(|x| x.is_finite()).fold(None, |acc, &x| match acc {
+        None => Some((x, x)),
+        Some((low, high)) => Some((low.min(x), high.max(x)))
+    })
+}
+
+/// Values at percentiles `p_low` and `p_high` of the finite values in `data`,
+/// estimated from at most `SAMPLES` evenly spaced elements.
+fn percentiles(data: &[f32], p_low: f32, p_high: f32) -> Option<(f32, f32)> {
+    const SAMPLES: usize = 16384;
+    let step = std::cmp::max(1, data.len() / SAMPLES);
+    let mut sample: Vec<f32> = data.iter().step_by(step).cloned().filter(|x| x.is_finite()).collect();
+    if sample.is_empty() {
+        return None;
+    }
+    sample.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let at = |p: f32| sample[((p / 100.0) * (sample.len() - 1) as f32).round() as usize];
+    Some((at(p_low.max(0.0).min(100.0)), at(p_high.max(0.0).min(100.0))))
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |v: &Vec<f32>| v.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",");
+        match self {
+            Normalization::Identity => write!(f, "identity"),
+            Normalization::ImageMinMax => write!(f, "image min/max"),
+            Normalization::DatasetMinMax => write!(f, "dataset min/max"),
+            Normalization::Window(low, high) => write!(f, "window [{}, {}]", low, high),
+            Normalization::Destandardize(mean, stdev) => write!(f, "x*[{}]+[{}]", join(stdev), join(mean)),
+            Normalization::Percentile(low, high) => write!(f, "percentile {}-{}%", low, high)
+        }
+    }
+}
+
+/// Parses `identity`, `image`, `dataset`, `window:MIN,MAX`,
+/// `percentile:LOW,HIGH` or `standard:MEAN,.../STD,...`.
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Normalization, String> {
+        let floats = |s: &str| s.split(',').map(|x| x.trim().parse::<f32>()).collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| format!("Invalid numbers \"{}\"", s));
+        let pair = |s: &str| floats(s).and_then(|v|
+            if v.len() == 2 { Ok((v[0], v[1])) }
+            else { Err(format!("Expected two numbers, got \"{}\"", s)) });
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap();
+        let args = parts.next().unwrap_or("");
+        match kind {
+            "identity" => Ok(Normalization::Identity),
+            "image" => Ok(Normalization::ImageMinMax),
+            "dataset" => Ok(Normalization::DatasetMinMax),
+            "window" => pair(args).map(|(low, high)| Normalization::Window(low, high)),
+            "percentile" => pair(args).map(|(low, high)| Normalization::Percentile(low, high)),
+            "standard" => {
+                let v: Vec<&str> = args.split('/').collect();
+                if v.len() != 2 {
+                    return Err(String::from("Expected standard:MEAN,.../STD,..."));
+                }
+                let (mean, stdev) = (floats(v[0])?, floats(v[1])?);
+                if mean.is_empty() || stdev.is_empty() {
+                    return Err(String::from("Expected standard:MEAN,.../STD,..."));
+                }
+                Ok(Normalization::Destandardize(mean, stdev))
+            },
+            _ => Err(format!("Unknown normalization \"{}\"", kind))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destandardizes_picked_channels_by_their_source() {
+        let normalization = Normalization::Destandardize(vec![10.0, 20.0, 30.0], vec![1.0, 2.0, 3.0]);
+        // one pixel of three channels, all 1 after standardization
+        let data = [1.0, 1.0, 1.0];
+        let (picked, sources) = ChannelMap::Gray(2).pick(&data, 3);
+        assert_eq!(normalization.apply(&picked, &sources, &Dtype::F4, None), vec![33]);
+        let (picked, sources) = ChannelMap::Rgb(2, 1, 0).pick(&data, 3);
+        assert_eq!(normalization.apply(&picked, &sources, &Dtype::F4, None), vec![33, 22, 11]);
+    }
+}
@@ -20,7 +20,9 @@ pub type VGUIFont = std::rc::Rc<rusttype::Font<'static>>;
 pub type TexImage = RgbaImage;
 
 pub const ENTRY_HEIGHT: u32 = 32;
+pub const CAPTION_HEIGHT: u32 = 20;
 pub const COLUMN_WIDTH: u32 = 315;
+pub const COLORBAR_HEIGHT: u32 = ENTRY_HEIGHT / 2 + ENTRY_HEIGHT;
 
 pub trait SpritePrototype {
     fn make_sprite<F, R>(&mut self, factory: &mut F) -> Sprite<Texture<R>>
@@ -102,6 +104,12 @@ impl Menu {
         (self.uuid_cursor.unwrap(), shift)
     }
 
+    pub fn seek(&mut self, label: &str) -> (uuid::Uuid, ai_behavior::Behavior<sprite::Animation>) {
+        let target = self.entries.iter().position(|e| e.label == label).unwrap_or(self.cursor);
+        let delta = target as i32 - self.cursor as i32;
+        self.mv(delta)
+    }
+
     pub fn get(&self) -> Option<String> {
         if self.entries.len() > 0 {
             Some(self.entries[self.cursor].label.clone())
@@ -142,10 +150,28 @@ pub fn load_font(fname: &str) -> Result<VGUIFont, rusttype::Error> {
     Ok(Rc::new(rusttype::FontCollection::from_bytes(buffer).unwrap().into_font()?))
 }
 
+/// Size items are enlarged to at least by `FlowLayout::fit_scale`, and shrunk
+/// to at most by zooming out.
+const MIN_TILE: u32 = 64;
+const MIN_ZOOMED_TILE: u32 = 8;
+
+/// Width of `text` drawn at `scale`, in pixels.
+pub fn text_width(font: &VGUIFont, scale: rusttype::Scale, text: &str) -> u32 {
+    let width: f32 = font.layout(text, scale, rusttype::point(0.0, 0.0))
+        .map(|g| g.unpositioned().h_metrics().advance_width).sum();
+    width.ceil() as u32
+}
+
 pub struct FlowLayout {
     pub view_size: (u32, u32),
     pub item_size: (u32, u32),
+    /// Display size of the items relative to `item_size`.
+    pub scale: f64,
+    /// Space below each item for its caption.
+    pub caption_height: u32,
     pub spacing: u32,
+    /// Sprite of the item at each position of the page, if drawn.
+    pub uuid_tiles: Vec<Option<uuid::Uuid>>,
     pub uuid_self: Option<uuid::Uuid>
 }
 
@@ -155,7 +181,10 @@ impl FlowLayout {
         FlowLayout {
             view_size: (1920, 1080),
             item_size: (100, 100),
+            scale: 1.0,
+            caption_height: 0,
             spacing: 6,
+            uuid_tiles: Vec::new(),
             uuid_self: None
         }
     }
@@ -165,25 +194,71 @@ impl FlowLayout {
         FlowLayout {
             view_size: (sz.0 as u32, sz.1 as u32),
             item_size: (100, 100),
+            scale: 1.0,
+            caption_height: 0,
             spacing: 6,
+            uuid_tiles: Vec::new(),
             uuid_self: None
         }
     }
 
+    /// Size items are displayed at.
+    pub fn tile_size(&self) -> (u32, u32) {
+        let scaled = |x: u32| std::cmp::max(1, (x as f64 * self.scale).round() as u32);
+        (scaled(self.item_size.0), scaled(self.item_size.1))
+    }
+
+    /// Largest scale showing a whole item.
+    fn max_scale(&self) -> f64 {
+        let (width, height) = (std::cmp::max(self.item_size.0, 1), std::cmp::max(self.item_size.1, 1));
+        (self.view_size.0.saturating_sub(self.spacing) as f64 / width as f64)
+            .min(self.view_size.1.saturating_sub(self.caption_height + self.spacing) as f64 / height as f64)
+    }
+
+    /// Enlarges small items by a whole factor to `MIN_TILE`, and shrinks
+    /// large ones into the view.
+    pub fn fit_scale(&mut self) {
+        let largest = std::cmp::max(std::cmp::max(self.item_size.0, self.item_size.1), 1);
+        self.scale = ((MIN_TILE + largest - 1) / largest) as f64;
+        self.scale = self.scale.min(self.max_scale());
+    }
+
+    /// Scales the items by `factor`, within what keeps them recognizable and
+    /// in view.
+    pub fn zoom(&mut self, factor: f64) {
+        let largest = std::cmp::max(std::cmp::max(self.item_size.0, self.item_size.1), 1);
+        let min_scale = MIN_ZOOMED_TILE as f64 / largest as f64;
+        self.scale = (self.scale * factor).max(min_scale).min(self.max_scale());
+    }
+
     pub fn get_items_per_row(&self) -> usize {
-        (self.view_size.0 / (self.item_size.0 + self.spacing)) as usize
+        std::cmp::max(1, (self.view_size.0 / (self.tile_size().0 + self.spacing)) as usize)
     }
 
     pub fn get_items_per_col(&self) -> usize {
-        (self.view_size.1 / (self.item_size.1 + self.spacing)) as usize
+        std::cmp::max(1, (self.view_size.1 / (self.tile_size().1 + self.caption_height + self.spacing)) as usize)
     }
 
     pub fn get_coordinate(&self, idx: usize) -> (f64, f64) {
         let items_per_row = self.get_items_per_row();
         let row = idx / items_per_row;
         let col = idx % items_per_row;
-        (col as f64 * (self.item_size.1 + self.spacing) as f64,
-         row as f64 * (self.item_size.0 + self.spacing) as f64)
+        let tile = self.tile_size();
+        (col as f64 * (tile.0 + self.spacing) as f64,
+         row as f64 * (tile.1 + self.caption_height + self.spacing) as f64)
+    }
+
+    /// Index of the item slot under `at`, relative to the layout.
+    pub fn get_index(&self, at: (f64, f64)) -> Option<usize> {
+        if at.0 < 0.0 || at.1 < 0.0 {
+            return None;
+        }
+        let tile = self.tile_size();
+        let (col, row) = ((at.0 as u32) / (tile.0 + self.spacing), (at.1 as u32) / (tile.1 + self.caption_height + self.spacing));
+        if (col as usize) < self.get_items_per_row() && (row as usize) < self.get_items_per_col() {
+            Some(row as usize * self.get_items_per_row() + col as usize)
+        }
+        else { None }
     }
 }
 
@@ -222,6 +297,196 @@ impl SpritePrototype for FlowLayout {
     }
 }
 
+/// Zoom range of `ImageView`, in screen pixels per image pixel.
+const MIN_ZOOM: f64 = 1.0 / 64.0;
+const MAX_ZOOM: f64 = 64.0;
+/// Zoom from which image pixels are drawn as blocks instead of blended.
+const NEAREST_ZOOM: f64 = 2.0;
+
+/// One image in a fixed viewport, zoomed and panned.
+pub struct ImageView {
+    pub view_size: (u32, u32),
+    /// Screen pixels per image pixel.
+    pub zoom: f64,
+    /// Image coordinates shown in the middle of the view.
+    pub center: (f64, f64),
+    /// Whether the image is scaled to fit, which new images keep.
+    pub fitted: bool,
+    image: Option<TexImage>,
+    /// Whether the texture of `image` samples nearest, once uploaded.
+    nearest: Option<bool>,
+    uuid_image: Option<uuid::Uuid>,
+    pub uuid_self: Option<uuid::Uuid>
+}
+
+impl ImageView {
+    pub fn new(view_size: (u32, u32)) -> ImageView {
+        ImageView {
+            view_size: view_size,
+            zoom: 1.0,
+            center: (0.0, 0.0),
+            fitted: true,
+            image: None,
+            nearest: None,
+            uuid_image: None,
+            uuid_self: None
+        }
+    }
+
+    /// Shows `image`, where the last one was if it has the same size.
+    pub fn set_image(&mut self, image: TexImage) {
+        let first = self.image.is_none();
+        let resized = self.image.as_ref().map(|im| im.dimensions() != image.dimensions()).unwrap_or(true);
+        self.image = Some(image);
+        self.nearest = None;
+        if first && !self.fitted {
+            self.top_left();
+        }
+        else if resized || self.fitted {
+            self.fit();
+        }
+    }
+
+    /// Forgets the image. The next one is scaled to fit, or shown 1:1 from
+    /// its top left corner.
+    pub fn clear(&mut self, fit: bool) {
+        self.image = None;
+        self.nearest = None;
+        self.fitted = fit;
+    }
+
+    /// One screen pixel per image pixel, the top left corners aligned.
+    fn top_left(&mut self) {
+        self.zoom = 1.0;
+        self.center = (self.view_size.0 as f64 / 2.0, self.view_size.1 as f64 / 2.0);
+        self.fitted = false;
+        self.clamp();
+    }
+
+    /// Scales the whole image into the view.
+    pub fn fit(&mut self) {
+        if let Some(im) = &self.image {
+            let (width, height) = im.dimensions();
+            self.zoom = (self.view_size.0 as f64 / width as f64).min(self.view_size.1 as f64 / height as f64);
+            self.center = (width as f64 / 2.0, height as f64 / 2.0);
+        }
+        self.fitted = true;
+    }
+
+    /// One screen pixel per image pixel, around the same center.
+    pub fn actual_size(&mut self) {
+        self.zoom = 1.0;
+        self.fitted = false;
+    }
+
+    /// Zooms by `factor`, keeping the image point under `at` in place.
+    pub fn zoom_at(&mut self, factor: f64, at: (f64, f64)) {
+        let zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+        let (dx, dy) = (at.0 - self.view_size.0 as f64 / 2.0, at.1 - self.view_size.1 as f64 / 2.0);
+        self.center.0 += dx / self.zoom - dx / zoom;
+        self.center.1 += dy / self.zoom - dy / zoom;
+        self.zoom = zoom;
+        self.fitted = false;
+        self.clamp();
+    }
+
+    /// Drags the image by `delta` screen pixels.
+    pub fn pan(&mut self, delta: (f64, f64)) {
+        self.center.0 -= delta.0 / self.zoom;
+        self.center.1 -= delta.1 / self.zoom;
+        self.fitted = false;
+        self.clamp();
+    }
+
+    /// Keeps some of the image in the middle of the view.
+    fn clamp(&mut self) {
+        if let Some(im) = &self.image {
+            let (width, height) = im.dimensions();
+            self.center.0 = self.center.0.max(0.0).min(width as f64);
+            self.center.1 = self.center.1.max(0.0).min(height as f64);
+        }
+    }
+
+    /// Pixel of the image under `at`, relative to the view.
+    pub fn get_pixel(&self, at: (f64, f64)) -> Option<(u32, u32)> {
+        let (width, height) = self.image.as_ref()?.dimensions();
+        let x = self.center.0 + (at.0 - self.view_size.0 as f64 / 2.0) / self.zoom;
+        let y = self.center.1 + (at.1 - self.view_size.1 as f64 / 2.0) / self.zoom;
+        if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+            Some((x as u32, y as u32))
+        }
+        else { None }
+    }
+
+    /// Whether `at`, relative to the view, is inside it.
+    pub fn contains(&self, at: (f64, f64)) -> bool {
+        at.0 >= 0.0 && at.1 >= 0.0 && at.0 < self.view_size.0 as f64 && at.1 < self.view_size.1 as f64
+    }
+
+    /// Moves and scales the image in `sprite`, the one made by `make_sprite`,
+    /// uploading it only when it is new or the sampling changes: nearest
+    /// from `NEAREST_ZOOM` on, linear below.
+    pub fn update<F, R>(&mut self, sprite: &mut Sprite<Texture<R>>, factory: &mut F)
+        where F: gfx::Factory<R>, R: gfx::Resources
+    {
+        let nearest = self.zoom >= NEAREST_ZOOM;
+        if self.nearest != Some(nearest) {
+            if let Some(id) = self.uuid_image.take() {
+                sprite.remove_child(id);
+            }
+            if let Some(im) = &self.image {
+                let filter = if nearest { Filter::Nearest } else { Filter::Linear };
+                let mut sprite_image = Sprite::from_texture(Rc::new(Texture::from_image(
+                    factory, im, &TextureSettings::new().filter(filter)).unwrap()));
+                sprite_image.set_anchor(0.0, 0.0);
+                self.uuid_image = Some(sprite.add_child(sprite_image));
+                self.nearest = Some(nearest);
+            }
+        }
+        if let Some(sprite_image) = self.uuid_image.and_then(|id| sprite.child_mut(id)) {
+            match self.visible_part() {
+                Some((src_rect, position)) => {
+                    sprite_image.set_src_rect(src_rect);
+                    sprite_image.set_position(position.0, position.1);
+                    sprite_image.set_scale(self.zoom, self.zoom);
+                    sprite_image.set_visible(true);
+                },
+                None => sprite_image.set_visible(false)
+            }
+        }
+    }
+
+    /// Part of the image inside the view, as x, y, width and height in the
+    /// image, with where it goes in the view.
+    fn visible_part(&self) -> Option<([f64; 4], (f64, f64))> {
+        let (width, height) = self.image.as_ref()?.dimensions();
+        let (view_w, view_h) = (self.view_size.0 as f64, self.view_size.1 as f64);
+        // image corners on screen
+        let left = view_w / 2.0 - self.center.0 * self.zoom;
+        let top = view_h / 2.0 - self.center.1 * self.zoom;
+        let (x0, y0) = ((-left / self.zoom).max(0.0), (-top / self.zoom).max(0.0));
+        let x1 = ((view_w - left) / self.zoom).min(width as f64);
+        let y1 = ((view_h - top) / self.zoom).min(height as f64);
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        Some(([x0, y0, x1 - x0, y1 - y0], (left + x0 * self.zoom, top + y0 * self.zoom)))
+    }
+}
+
+impl SpritePrototype for ImageView {
+    fn make_sprite<F, R>(&mut self, factory: &mut F) -> Sprite<Texture<R>>
+        where F: gfx::Factory<R>, R: gfx::Resources
+    {
+        let mut sprite = Sprite::from_texture(Rc::new(Texture::empty(factory).unwrap()));
+        sprite.set_anchor(0.0, 0.0);
+        self.uuid_image = None;
+        self.nearest = None;
+        self.update(&mut sprite, factory);
+        return sprite;
+    }
+}
+
 pub struct StatusBar {
     pub label: String,
     pub font: VGUIFont,
@@ -268,6 +533,66 @@ impl SpritePrototype for StatusBar {
     }
 }
 
+/// Legend of a colormap: its colors from left to right, and the values at
+/// either end.
+pub struct Colorbar {
+    pub gradient: Vec<[u8; 3]>,
+    pub low: String,
+    pub high: String,
+    pub font: VGUIFont,
+    pub width: u32
+}
+
+impl Colorbar {
+    pub fn update<F, R>(&mut self, gradient: Vec<[u8; 3]>, low: String, high: String,
+        sprite: &mut Sprite<Texture<R>>, factory: &mut F)
+        where F: gfx::Factory<R>, R: gfx::Resources
+    {
+        self.gradient = gradient;
+        self.low = low;
+        self.high = high;
+        sprite.set_texture(
+            Rc::new(Texture::from_image(
+            factory,
+            &self.draw(),
+            &TextureSettings::new()
+        ).unwrap()));
+    }
+
+    fn draw(&mut self) -> TexImage {
+        const BAR_HEIGHT: u32 = COLORBAR_HEIGHT - ENTRY_HEIGHT;
+        let mut image = RgbaImage::new(self.width, COLORBAR_HEIGHT);
+        if !self.gradient.is_empty() {
+            for x in 0..self.width {
+                let [r, g, b] = self.gradient[x as usize * self.gradient.len() / self.width as usize];
+                for y in 0..BAR_HEIGHT {
+                    image.put_pixel(x, y, Rgba([r, g, b, 255u8]));
+                }
+            }
+        }
+        let scale = rusttype::Scale { x: ENTRY_HEIGHT as f32 * 0.75, y: ENTRY_HEIGHT as f32 * 0.75 };
+        let color = Rgba([0u8, 0u8, 0u8, 255u8]);
+        imageproc::drawing::draw_text_mut(&mut image, color, 0, BAR_HEIGHT + 2, scale, self.font.borrow(), &self.low);
+        imageproc::drawing::draw_text_mut(&mut image, color, self.width.saturating_sub(text_width(&self.font, scale, &self.high)),
+            BAR_HEIGHT + 2, scale, self.font.borrow(), &self.high);
+        return image;
+    }
+}
+
+impl SpritePrototype for Colorbar {
+    fn make_sprite<F, R>(&mut self, factory: &mut F) -> Sprite<Texture<R>>
+        where F: gfx::Factory<R>, R: gfx::Resources
+    {
+        let mut sprite = Sprite::from_texture(Rc::new(Texture::from_image(
+            factory,
+            &self.draw(),
+            &TextureSettings::new()
+        ).unwrap()));
+        sprite.set_anchor(0.0, 0.0);
+        return sprite;
+    }
+}
+
 pub struct Pagnator {
     pub total_items: usize,
     pub page_size: usize,
@@ -275,8 +600,16 @@ pub struct Pagnator {
 }
 
 impl Pagnator {
-    pub fn new(o: &Paginatable, total_items: usize) -> Pagnator {
-        Pagnator { total_items: total_items, page_size: o.page_capacity(), page_current: 0 }
+    pub fn new(o: &dyn Paginatable, total_items: usize) -> Pagnator {
+        Pagnator { total_items: total_items, page_size: std::cmp::max(1, o.page_capacity()), page_current: 0 }
+    }
+
+    /// Changes the page size, moving to the page holding item `index`, and
+    /// returns its position on that page.
+    pub fn set_page_size(&mut self, page_size: usize, index: usize) -> usize {
+        self.page_size = std::cmp::max(1, page_size);
+        self.page_current = index / self.page_size;
+        index % self.page_size
     }
 
     pub fn inc(&mut self) {
@@ -306,6 +639,31 @@ impl Pagnator {
     }
 }
 
+/// Text centered on a `width` wide strip, to go under an item.
+pub fn caption(text: &str, font: &VGUIFont, width: u32) -> TexImage {
+    let mut image = RgbaImage::new(width, CAPTION_HEIGHT);
+    let scale = rusttype::Scale { x: CAPTION_HEIGHT as f32 * 0.9, y: CAPTION_HEIGHT as f32 * 0.9 };
+    let x = width.saturating_sub(text_width(font, scale, text)) / 2;
+    imageproc::drawing::draw_text_mut(&mut image, Rgba([0u8, 0u8, 0u8, 255u8]), x, 0, scale, font.borrow(), text);
+    image
+}
+
+/// Frame around the selected item of a FlowLayout of `item_size` items.
+pub fn highlight(item_size: (u32, u32), spacing: u32) -> TexImage {
+    let (width, height) = (item_size.0 + spacing, item_size.1 + spacing);
+    let mut image = RgbaImage::new(width, height);
+    let color = Rgba([0u8, 0u8, 255u8, 255u8]);
+    for i in 0..spacing / 2 {
+        imageproc::drawing::draw_hollow_rect_mut(&mut image, Rect::at(i as i32, i as i32).of_size(width - 2 * i, height - 2 * i), color);
+    }
+    image
+}
+
+/// Flat tile standing in for an image that is not there yet.
+pub fn placeholder(size: (u32, u32)) -> TexImage {
+    RgbaImage::from_pixel(size.0, size.1, Rgba([224u8, 224u8, 224u8, 255u8]))
+}
+
 pub fn sprite_from_image<F, R>(im: &TexImage, factory: &mut F) -> Sprite<Texture<R>>
     where F: gfx::Factory<R>, R: gfx::Resources
 {